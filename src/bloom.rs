@@ -0,0 +1,239 @@
+use macroquad::{
+    camera::{self, Camera2D},
+    color::{colors, Color},
+    material::{self, Material, MaterialParams},
+    math::vec2,
+    texture::{self, DrawTextureParams, RenderTarget, Texture2D},
+    window::{
+        self,
+        miniquad::{BlendFactor, BlendState, Equation, PipelineParams, ShaderSource, UniformType},
+    },
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// How bright the glow is compared to the particles.
+    pub intensity: f32,
+    /// The brightness that is subtracted from each color channel before it is blurred, so only
+    /// brighter colors glow.
+    pub threshold: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 1.0,
+            threshold: 0.2,
+        }
+    }
+}
+
+/// A post processing pass that blurs the scene at half resolution and adds it back on top of
+/// itself.
+///
+/// Particles are drawn into the scene with premultiplied alpha (see `premultiplied`), and
+/// everything else with macroquad's usual straight alpha. The scene starts out opaque, so both
+/// leave straight colors with an alpha of 1 in it. The blur still premultiplies each sample by
+/// its alpha before averaging, so that transparent parts of the scene wouldn't spread their color
+/// into the glow. Everything after that, including the composite, works with premultiplied
+/// colors.
+pub struct Bloom {
+    size: [u32; 2],
+    scene: RenderTarget,
+    blur: [RenderTarget; 2],
+    blur_material: Material,
+    composite_material: Material,
+}
+
+impl Bloom {
+    pub fn new() -> Self {
+        let blur_material = material::load_material(
+            ShaderSource::Glsl {
+                vertex: VERTEX_SHADER,
+                fragment: BLUR_FRAGMENT_SHADER,
+            },
+            MaterialParams {
+                uniforms: vec![
+                    ("Direction".to_string(), UniformType::Float2),
+                    ("Threshold".to_string(), UniformType::Float1),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let composite_material = material::load_material(
+            ShaderSource::Glsl {
+                vertex: VERTEX_SHADER,
+                fragment: COMPOSITE_FRAGMENT_SHADER,
+            },
+            MaterialParams {
+                uniforms: vec![("Intensity".to_string(), UniformType::Float1)],
+                pipeline_params: PipelineParams {
+                    // The blurred texture is premultiplied, so it's added to the scene as it is,
+                    // without being multiplied by its alpha like straight colors would be
+                    color_blend: Some(BlendState::new(
+                        Equation::Add,
+                        BlendFactor::One,
+                        BlendFactor::One,
+                    )),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let size = Self::screen_size();
+        let (scene, blur) = Self::create_targets(size);
+        Self {
+            size,
+            scene,
+            blur,
+            blur_material,
+            composite_material,
+        }
+    }
+
+    /// Sets up a camera matching `camera` that draws to the scene, which is shown once `finish`
+    /// is called.
+    pub fn begin_scene(&mut self, camera: &Camera2D, background_color: Color) {
+        let size = Self::screen_size();
+        if size != self.size {
+            let (scene, blur) = Self::create_targets(size);
+            self.size = size;
+            self.scene = scene;
+            self.blur = blur;
+        }
+
+        camera::set_camera(&Camera2D {
+            rotation: camera.rotation,
+            zoom: camera.zoom,
+            target: camera.target,
+            offset: camera.offset,
+            render_target: Some(self.scene.clone()),
+            ..Default::default()
+        });
+        window::clear_background(background_color);
+    }
+
+    /// Draws the scene with the glow to the screen. This leaves the default camera set.
+    pub fn finish(&self, settings: &BloomSettings) {
+        let blur_size = self.size.map(|x| (x / 2).max(1));
+
+        // Horizontal blur of the bright parts of the scene
+        material::gl_use_material(&self.blur_material);
+        self.blur_material
+            .set_uniform("Direction", vec2(2.0 / self.size[0] as f32, 0.0));
+        self.blur_material
+            .set_uniform("Threshold", settings.threshold);
+        Self::draw_to_target(&self.scene.texture, &self.blur[0], blur_size);
+
+        // Vertical blur
+        self.blur_material
+            .set_uniform("Direction", vec2(0.0, 2.0 / blur_size[1] as f32));
+        self.blur_material.set_uniform("Threshold", 0.0f32);
+        Self::draw_to_target(&self.blur[0].texture, &self.blur[1], blur_size);
+        material::gl_use_default_material();
+
+        // Composite
+        camera::set_default_camera();
+        let params = DrawTextureParams {
+            dest_size: Some(vec2(window::screen_width(), window::screen_height())),
+            ..Default::default()
+        };
+        texture::draw_texture_ex(&self.scene.texture, 0.0, 0.0, colors::WHITE, params.clone());
+        material::gl_use_material(&self.composite_material);
+        self.composite_material
+            .set_uniform("Intensity", settings.intensity);
+        texture::draw_texture_ex(&self.blur[1].texture, 0.0, 0.0, colors::WHITE, params);
+        material::gl_use_default_material();
+    }
+
+    fn draw_to_target(texture: &Texture2D, target: &RenderTarget, size: [u32; 2]) {
+        let size = vec2(size[0] as f32, size[1] as f32);
+        camera::set_camera(&Camera2D {
+            zoom: 2.0 / size,
+            target: size / 2.0,
+            render_target: Some(target.clone()),
+            ..Default::default()
+        });
+        window::clear_background(colors::BLACK);
+        texture::draw_texture_ex(
+            texture,
+            0.0,
+            0.0,
+            colors::WHITE,
+            DrawTextureParams {
+                dest_size: Some(size),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn screen_size() -> [u32; 2] {
+        [window::screen_width(), window::screen_height()].map(|x| (x as u32).max(1))
+    }
+
+    fn create_targets(size: [u32; 2]) -> (RenderTarget, [RenderTarget; 2]) {
+        let blur_size = size.map(|x| (x / 2).max(1));
+        (
+            texture::render_target(size[0], size[1]),
+            [(); 2].map(|_| texture::render_target(blur_size[0], blur_size[1])),
+        )
+    }
+}
+
+const VERTEX_SHADER: &str = "#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+
+varying lowp vec2 uv;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    uv = texcoord;
+}
+";
+
+// Gaussian blur along one axis, using linear filtering to sample between texels. Samples are
+// premultiplied by their alpha, and the output is premultiplied with an alpha of 1.
+const BLUR_FRAGMENT_SHADER: &str = "#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec2 Direction;
+uniform float Threshold;
+
+vec3 bright(vec2 offset) {
+    vec4 color = texture2D(Texture, uv + offset);
+    return max(color.rgb * color.a - Threshold, 0.0);
+}
+
+void main() {
+    vec3 sum = bright(vec2(0.0)) * 0.2270270;
+    sum += (bright(Direction * 1.3846154) + bright(-Direction * 1.3846154)) * 0.3162162;
+    sum += (bright(Direction * 3.2307692) + bright(-Direction * 3.2307692)) * 0.0702703;
+    gl_FragColor = vec4(sum, 1.0);
+}
+";
+
+const COMPOSITE_FRAGMENT_SHADER: &str = "#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+uniform float Intensity;
+
+void main() {
+    gl_FragColor = vec4(texture2D(Texture, uv).rgb * Intensity, 1.0);
+}
+";
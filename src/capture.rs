@@ -0,0 +1,20 @@
+use macroquad::{miniquad, texture};
+use std::{fs, fs::File, path::PathBuf};
+
+pub const CAPTURE_PATH: &str = "particle_life_view.png";
+
+/// Copies what has been drawn so far this frame, without the ui, and returns a message for the
+/// user. The clipboard only holds text on the platforms macroquad supports, so the frame is
+/// always saved to `CAPTURE_PATH` as a png in stead, and the full path to it is copied.
+pub fn copy_view_to_clipboard() -> String {
+    // `export_png` panics if the file can't be written, so make sure it can be first
+    if let Err(error) = File::create(CAPTURE_PATH) {
+        return format!("Failed to save the view: {error}");
+    }
+    texture::get_screen_data().export_png(CAPTURE_PATH);
+
+    let path = fs::canonicalize(CAPTURE_PATH).unwrap_or_else(|_| PathBuf::from(CAPTURE_PATH));
+    let path = path.display().to_string();
+    miniquad::window::clipboard_set(&path);
+    format!("Images can't be copied here, so the view was saved to {path} and its path copied")
+}
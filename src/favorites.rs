@@ -0,0 +1,163 @@
+use crate::settings::{format_color, parse_color};
+use macroquad::color::Color;
+use particle_life::particle_simulation::ParticleTypeData;
+use std::{fs, io};
+
+pub const FAVORITES_PATH: &str = "particle_life_favorites.txt";
+
+/// A named copy of some type data, kept so it can be loaded again later.
+#[derive(Clone, Debug)]
+pub struct Favorite {
+    pub name: String,
+    pub type_data: ParticleTypeData,
+}
+
+/// The saved favorites, in the order they were added. Stored in the same `key = value` format as
+/// `AppSettings`, where each `name` key starts a new favorite followed by its `attractions` (a
+/// string from `ParticleTypeData::to_preset_string`) and `colors` (separated by commas).
+#[derive(Clone, Debug, Default)]
+pub struct Favorites {
+    pub entries: Vec<Favorite>,
+}
+
+impl Favorites {
+    /// Loads the favorites file. Favorites that can't be read are skipped, and a missing file
+    /// means there are no favorites.
+    pub fn load() -> Self {
+        fs::read_to_string(FAVORITES_PATH)
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(FAVORITES_PATH, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut name = None;
+        let mut type_data = None;
+        let mut colors = None;
+        let mut finish = |name: Option<String>, type_data, colors| {
+            if let Some(favorite) = name.and_then(|name| Favorite::new(name, type_data?, colors)) {
+                entries.push(favorite);
+            }
+        };
+
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "name" => {
+                    finish(name.take(), type_data.take(), colors.take());
+                    name = Some(value.to_string());
+                }
+                "attractions" => type_data = ParticleTypeData::from_preset_string(value),
+                "colors" => {
+                    colors = value
+                        .split(',')
+                        .map(parse_color)
+                        .collect::<Option<Box<[_]>>>()
+                }
+                _ => (),
+            }
+        }
+        finish(name, type_data, colors);
+        Self { entries }
+    }
+}
+
+impl Favorite {
+    /// Uses `colors` in stead of the default colors of `type_data` if there are the right number
+    /// of them. Returns `None` if `name` is empty.
+    fn new(
+        name: String,
+        type_data: ParticleTypeData,
+        colors: Option<Box<[Color]>>,
+    ) -> Option<Self> {
+        if name.is_empty() {
+            return None;
+        }
+        let type_data = colors
+            .and_then(|colors| {
+                let attractions = type_data.base_attractions();
+                let mut with_colors = ParticleTypeData::new_with_colors(
+                    type_data.num_types(),
+                    type_data.positive_scale(),
+                    |index| attractions[index],
+                    colors,
+                )?;
+                with_colors.set_negative_scale(type_data.negative_scale());
+                Some(with_colors)
+            })
+            .unwrap_or(type_data);
+        Some(Self { name, type_data })
+    }
+}
+
+impl std::fmt::Display for Favorites {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for favorite in &self.entries {
+            // Names are single lines, so they can't break the format
+            writeln!(f, "name = {}", favorite.name.replace(['\n', '\r'], " "))?;
+            writeln!(f, "attractions = {}", favorite.type_data.to_preset_string())?;
+            let colors: Vec<_> = favorite
+                .type_data
+                .colors()
+                .iter()
+                .map(|&color| format_color(color))
+                .collect();
+            writeln!(f, "colors = {}", colors.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_favorite_round_trip() {
+        let colors: Box<[Color]> = Box::new([
+            Color::new(1.0, 0.0, 0.0, 1.0),
+            Color::new(0.25, 0.5, 0.75, 1.0),
+            Color::new(0.0, 0.0, 1.0, 0.5),
+        ]);
+        let mut type_data = ParticleTypeData::new_with_colors(
+            3,
+            2.0,
+            |[source, target]| source as f64 * 0.25 - target as f64 * 0.5,
+            colors,
+        )
+        .unwrap();
+        type_data.set_negative_scale(3.0);
+        let favorites = Favorites {
+            entries: vec![Favorite {
+                name: "Spirals".to_string(),
+                type_data,
+            }],
+        };
+
+        let parsed = Favorites::parse(&favorites.to_string());
+        assert_eq!(parsed.entries.len(), 1);
+        let (favorite, original) = (&parsed.entries[0], &favorites.entries[0]);
+        assert_eq!(favorite.name, "Spirals");
+        assert_eq!(
+            favorite.type_data.base_attractions().data,
+            original.type_data.base_attractions().data
+        );
+        assert_eq!(favorite.type_data.positive_scale(), 2.0);
+        assert_eq!(favorite.type_data.negative_scale(), 3.0);
+        assert_eq!(favorite.type_data.colors(), original.type_data.colors());
+    }
+
+    #[test]
+    fn unreadable_favorites_are_skipped() {
+        let text =
+            "name = Broken\nattractions = not base64\nname =\nattractions = AAAAAAAAAAAAAAAA\n";
+        assert!(Favorites::parse(text).entries.is_empty());
+    }
+}
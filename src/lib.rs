@@ -0,0 +1,2 @@
+pub mod matrix;
+pub mod particle_simulation;
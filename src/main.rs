@@ -1,20 +1,35 @@
+use bloom::Bloom;
+use favorites::Favorites;
 use macroquad::{
     camera::{self, Camera2D},
     color::colors,
-    input::{self, KeyCode},
+    input::{self, KeyCode, MouseButton},
     math::{vec2, Vec2},
-    text, time,
+    shapes, text, time,
     window::{self, Conf},
 };
-use particle_simulation::{EdgeType, ParticleSimulation, ParticleSimulationParams};
+use particle_life::particle_simulation::{
+    analysis::OrbitDetector, AttractionMorph, ParticleSimulation, ParticleTypeData, PARTICLE_RADIUS,
+};
+use recording::MetricRecorder;
+use session::Session;
+use settings::AppSettings;
 use std::{
+    io,
     sync::{Arc, Mutex},
     thread,
     time::{Duration, Instant},
 };
+use ui::{SessionAction, UiState};
 
-pub(crate) mod matrix;
-pub(crate) mod particle_simulation;
+pub(crate) mod bloom;
+pub(crate) mod capture;
+pub(crate) mod favorites;
+pub(crate) mod recording;
+pub(crate) mod session;
+pub(crate) mod settings;
+pub(crate) mod ui;
+pub(crate) mod view;
 
 fn window_conf() -> Conf {
     Conf {
@@ -24,39 +39,28 @@ fn window_conf() -> Conf {
 }
 
 fn simulation_from_size(size: [usize; 2], density: f64) -> ParticleSimulation {
-    let bucket_size: f64 = 100.0;
-    let buckets = size[0] * size[1];
-    let area = buckets as f64 * bucket_size.powi(2);
-    let particle_count = area * density;
-    let particle_count = particle_count as usize;
-    let mut particle_simulation = ParticleSimulation::new(
-        bucket_size,
-        size,
-        ParticleSimulationParams {
-            edge_type: EdgeType::Bouncing {
-                multiplier: 1.0,
-                pushback: 2.5,
-            },
-            prevent_particle_ejecting: true,
-        },
-        50,
-        5.0,
-    );
-    particle_simulation.add_random_particles(particle_count);
-    particle_simulation
+    ParticleSimulation::builder()
+        .buckets(size)
+        .density(density)
+        .build()
 }
 
-fn new_simulation() -> ParticleSimulation {
-    simulation_from_size([30, 20], 2e-3)
+pub const DEFAULT_GRID_SIZE: [usize; 2] = [30, 20];
+
+/// The default TPS limit, and the tick rate `TimeDilation` treats as normal.
+pub const TICK_RATE: f64 = 30.0;
+
+fn new_simulation(grid_size: [usize; 2]) -> ParticleSimulation {
+    simulation_from_size(grid_size, 2e-3)
 }
 
 #[macroquad::main(window_conf)]
 async fn main() {
-    let simulation = new_simulation();
+    let simulation = new_simulation(DEFAULT_GRID_SIZE);
     let thread_data = SimulationThreadData::default();
 
     let mut camera = Camera2D::default();
-    center_camera(&mut camera, simulation.size_vec2());
+    view::center_camera(&mut camera, simulation.world_bounds());
 
     let simulation_mutex = Arc::new(Mutex::new(simulation));
     let thread_data_mutex = Arc::new(Mutex::new(thread_data));
@@ -65,41 +69,86 @@ async fn main() {
     let simulation_reference = Arc::clone(&simulation_mutex);
     let thread_data_reference = Arc::clone(&thread_data_mutex);
     let simulation_thread = thread::spawn(move || {
-        let update_time = Duration::from_secs_f64(1.0 / 30.0);
+        let mut update_time = Duration::from_secs_f64(1.0 / TICK_RATE);
 
         let mut simulation_buffer = (*simulation_reference.lock().unwrap()).clone();
 
         let mut time = None;
+        let mut falling_behind = false;
         let mut frame_end;
         loop {
             // Set the target frame end time
             frame_end = Instant::now() + update_time;
 
             let start = Instant::now();
+            let mut idle = false;
 
             'update: {
                 'simulate: {
+                    let edits;
+                    let active;
                     {
                         let mut thread_data = thread_data_reference.lock().unwrap();
                         if thread_data.active {
                             thread_data.tick_time = time;
+                            thread_data.falling_behind = falling_behind;
                         } else {
                             thread_data.tick_time = None;
+                            thread_data.falling_behind = false;
                         }
 
                         if thread_data.reset {
-                            simulation_buffer = new_simulation();
+                            simulation_buffer = new_simulation(thread_data.grid_size);
                             thread_data.reset = false;
                             break 'simulate;
                         }
 
-                        if !thread_data.active {
-                            break 'update;
+                        update_time = match thread_data.time_dilation {
+                            Some(dilation) => {
+                                let (energy, _) = simulation_buffer.kinetic_energy_stats();
+                                Duration::from_secs_f64(1.0 / dilation.tick_rate(energy))
+                            }
+                            None if thread_data.tps_limit > 0.0 => {
+                                Duration::from_secs_f64(1.0 / thread_data.tps_limit)
+                            }
+                            None => Duration::ZERO,
+                        };
+
+                        edits = std::mem::take(&mut thread_data.edits);
+                        active = thread_data.take_step();
+
+                        if active {
+                            if let Some(morph) = &mut thread_data.morph {
+                                if morph.step(simulation_buffer.type_data_mut()) {
+                                    thread_data.morph = None;
+                                }
+                            }
+                            if let Some(recorder) = &mut thread_data.recorder {
+                                if let Err(error) = recorder.record(&simulation_buffer) {
+                                    thread_data.recorder = None;
+                                    thread_data.recording_error = Some(error);
+                                }
+                            }
+                            if let Some(detector) = &mut thread_data.orbits {
+                                detector.record(&simulation_buffer);
+                            }
                         }
                     }
 
+                    if !active && edits.is_empty() {
+                        idle = true;
+                        break 'update;
+                    }
+
+                    // Apply edits from the ui
+                    for edit in edits {
+                        edit(&mut simulation_buffer);
+                    }
+
                     // Update buffer
-                    simulation_buffer.step_simulation();
+                    if active {
+                        simulation_buffer.step_simulation();
+                    }
                 }
 
                 // Copy buffer to shared state
@@ -107,32 +156,67 @@ async fn main() {
                     let mut simulation_reference = simulation_reference.lock().unwrap();
                     *simulation_reference = simulation_buffer.clone();
                 }
+                thread_data_reference.lock().unwrap().update_count += 1;
+            }
+
+            // While paused, only check for edits at the default tick rate, so that a high or
+            // unlimited TPS limit doesn't keep the thread busy doing nothing
+            if idle {
+                frame_end = frame_end.max(start + Duration::from_secs_f64(1.0 / TICK_RATE));
             }
 
             // Wait if there's time left
-            thread::sleep(frame_end - Instant::now());
+            let now = Instant::now();
+            falling_behind = !update_time.is_zero() && now > frame_end;
+            thread::sleep(frame_end.saturating_duration_since(now));
 
             time = Some(Instant::now() - start);
         }
     });
 
     let mut debug_mode: u8 = 0;
+    let mut settings = AppSettings::load();
     let mut fullscreen = false;
+    let mut follow_center_of_mass = false;
+    let mut ui_state = UiState {
+        favorites: Favorites::load(),
+        ..Default::default()
+    };
+    let mut bloom = None;
+    let mut last_activity = Instant::now();
+    let mut last_update_count = 0;
+    let mut frame_deadline = Instant::now();
+    let mut screen_size = vec2(window::screen_width(), window::screen_height());
+    // Whether the camera still shows what `view::center_camera` framed
+    let mut camera_fitted = true;
+    // A message shown at the bottom of the screen for a while, like where the view was copied to
+    let mut notice: Option<(String, Instant)> = None;
 
     // Rendering and user input
     let simulation_reference = Arc::clone(&simulation_mutex);
     let thread_data_reference = Arc::clone(&thread_data_mutex);
     loop {
+        let frame_start = Instant::now();
+
         // Panic if the simulation thread is no longer running
         if simulation_thread.is_finished() {
             panic!("Simulation thread panicked");
         }
 
+        // Hotkeys are ignored while typing into a text field
+        let typing = ui_state.text_focus;
+        let key_pressed = |key| !typing && input::is_key_pressed(key);
+
         // Camera control
-        update_camera_control(&mut camera, 1.0, 0.1);
+        let zoom = camera.zoom.y;
+        if update_camera_control(&mut camera, 1.0, 0.1, typing) {
+            follow_center_of_mass = false;
+            camera_fitted = false;
+        }
+        camera_fitted &= camera.zoom.y == zoom;
 
         // Setup camera
-        update_camera_aspect_ratio(&mut camera);
+        view::update_aspect_ratio(&mut camera);
         camera::set_camera(&camera);
 
         // Copy simulation to buffer
@@ -143,41 +227,220 @@ async fn main() {
 
         // Update thread_data
         let tick_time;
+        let falling_behind;
+        let paused;
         {
             let mut thread_data = thread_data_reference.lock().unwrap();
-            thread_data.active ^= input::is_key_pressed(KeyCode::Space);
-            thread_data.reset |= input::is_key_pressed(KeyCode::R);
+            if thread_data.update_count != last_update_count {
+                last_update_count = thread_data.update_count;
+                last_activity = Instant::now();
+            }
+            paused = !thread_data.active;
+
+            thread_data.active ^= key_pressed(KeyCode::Space);
+            thread_data.reset |= key_pressed(KeyCode::R);
             tick_time = thread_data.tick_time;
+            falling_behind = thread_data.falling_behind;
+
+            // Impulses are only recorded while they are drawn, since recording them slows the
+            // simulation down
+            let draw_impulses = settings.draw_settings.draw_impulses;
+            if draw_impulses != simulation_buffer.impulses().is_some() {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_record_impulses(draw_impulses)
+                }));
+            }
+            thread_data.tps_limit = settings.tps_limit as f64;
+
+            ui_state.info_window_open ^= key_pressed(KeyCode::F1);
+            ui::update_info_window(
+                &mut ui_state,
+                &mut settings,
+                &simulation_buffer,
+                &mut thread_data,
+            );
+            ui::update_hotkeys(&mut ui_state, &simulation_buffer, &mut thread_data);
+            match ui_state.session_action.take() {
+                Some(SessionAction::Save) => {
+                    let session = Session {
+                        camera_target: camera.target,
+                        camera_zoom: camera.zoom.y,
+                        follow_center_of_mass,
+                        debug_mode,
+                        info_window_open: ui_state.info_window_open,
+                        brush_type: ui_state.brush_type,
+                        attractions: Some(simulation_buffer.type_data().to_preset_string()),
+                    };
+                    ui_state.session_status = match session.save() {
+                        Ok(()) => "Saved session".to_string(),
+                        Err(error) => format!("Failed to save: {error}"),
+                    };
+                }
+                Some(SessionAction::Load) => {
+                    if let Some(session) = Session::load() {
+                        camera.target = session.camera_target;
+                        camera.zoom = Vec2::splat(session.camera_zoom);
+                        view::update_aspect_ratio(&mut camera);
+                        camera_fitted = false;
+                        follow_center_of_mass = session.follow_center_of_mass;
+                        debug_mode = session.debug_mode;
+                        settings.draw_settings.draw_bucket_edges = debug_mode > 1;
+                        ui_state.info_window_open = session.info_window_open;
+                        ui_state.brush_type = session.brush_type;
+                        ui_state.session_status = "Loaded session".to_string();
+                        if let Some(preset) = session
+                            .attractions
+                            .as_deref()
+                            .and_then(ParticleTypeData::from_preset_string)
+                        {
+                            match ui::check_preset(&simulation_buffer, &preset) {
+                                Ok(()) => thread_data.edits.push(Box::new(move |simulation| {
+                                    let type_data = simulation.type_data_mut();
+                                    type_data
+                                        .set_base_attractions(preset.base_attractions().clone());
+                                    type_data.set_positive_scale(preset.positive_scale());
+                                    type_data.set_negative_scale(preset.negative_scale());
+                                })),
+                                Err(error) => {
+                                    ui_state.session_status =
+                                        format!("Loaded session without its attractions: {error}")
+                                }
+                            }
+                        }
+                    } else {
+                        ui_state.session_status = "No session to load".to_string();
+                    }
+                }
+                None => (),
+            }
+            ui::update_brush(&mut ui_state, &camera, &simulation_buffer, &mut thread_data);
+            ui::update_selection(&mut ui_state, &camera, &simulation_buffer);
         }
 
-        if input::is_key_pressed(KeyCode::F11) {
+        if key_pressed(KeyCode::F11) {
             fullscreen ^= true;
             window::set_fullscreen(fullscreen);
             input::show_mouse(!fullscreen);
         }
 
         // Center control
-        if input::is_key_pressed(KeyCode::C) {
-            center_camera(&mut camera, simulation_buffer.size_vec2());
+        if key_pressed(KeyCode::C) {
+            view::center_camera(&mut camera, simulation_buffer.world_bounds());
+            camera_fitted = true;
         }
 
-        if input::is_key_pressed(KeyCode::F3) {
-            let mode;
-            if input::is_key_down(KeyCode::LeftShift) {
-                mode = 2;
-            } else {
-                mode = 1;
+        let new_screen_size = vec2(window::screen_width(), window::screen_height());
+        if new_screen_size != screen_size {
+            screen_size = new_screen_size;
+            if settings.refit_on_resize && camera_fitted {
+                view::center_camera(&mut camera, simulation_buffer.world_bounds());
             }
+        }
+
+        // Zoom presets, unless the number keys are picking brush types
+        if !ui::brush_active() {
+            for (key, radius) in [KeyCode::Key1, KeyCode::Key2]
+                .into_iter()
+                .zip(settings.zoom_presets)
+            {
+                if key_pressed(key) {
+                    view::set_pixels_per_unit(&mut camera, radius / PARTICLE_RADIUS as f32);
+                    camera_fitted = false;
+                }
+            }
+        }
+
+        // Center of mass tracking
+        let target = camera.target;
+        follow_center_of_mass ^= key_pressed(KeyCode::M);
+        if follow_center_of_mass {
+            if let Some(center) = simulation_buffer.center_of_mass() {
+                let center = vec2(center[0] as f32, center[1] as f32);
+                let amount = 1.0 - (-5.0 * time::get_frame_time()).exp();
+                camera.target = camera.target.lerp(center, amount);
+            }
+        }
+
+        if settings.camera_bounds {
+            view::pull_camera_into_bounds(&mut camera, simulation_buffer.world_bounds());
+        }
+        camera_fitted &= camera.target == target;
+
+        if key_pressed(KeyCode::F3) {
+            let mode = if input::is_key_down(KeyCode::LeftShift) {
+                2
+            } else {
+                1
+            };
 
             if debug_mode >= mode {
                 debug_mode = 0;
             } else {
                 debug_mode = mode;
             }
+            settings.draw_settings.draw_bucket_edges = debug_mode > 1;
+        }
+
+        // Flow field control
+        settings.draw_settings.draw_flow_field ^= key_pressed(KeyCode::F4);
+        if settings.draw_settings.draw_flow_field {
+            if key_pressed(KeyCode::Equal) {
+                settings.draw_settings.flow_field_scale *= 1.5;
+            }
+            if key_pressed(KeyCode::Minus) {
+                settings.draw_settings.flow_field_scale /= 1.5;
+            }
+        }
+
+        // Bucket overlay control
+        if key_pressed(KeyCode::F5) {
+            settings.draw_settings.bucket_overlay = settings.draw_settings.bucket_overlay.next();
         }
 
         // Rendering
-        simulation_buffer.draw_at(vec2(0.0, 0.0), &camera, debug_mode > 1);
+        window::clear_background(settings.background_color);
+        if settings.bloom.enabled {
+            bloom
+                .get_or_insert_with(Bloom::new)
+                .begin_scene(&camera, settings.background_color);
+        }
+        let draw_stats =
+            simulation_buffer.draw_at(vec2(0.0, 0.0), &camera, &settings.draw_settings);
+        if debug_mode > 0 && settings.draw_settings.draw_interaction_range {
+            let cursor = view::cursor_position(&camera);
+            if let Some(particle) =
+                simulation_buffer.nearest_particle([cursor.x as f64, cursor.y as f64])
+            {
+                simulation_buffer.draw_interaction_range(
+                    vec2(0.0, 0.0),
+                    &camera,
+                    particle,
+                    &settings.draw_settings,
+                );
+            }
+        }
+        if let (true, Some(bloom)) = (settings.bloom.enabled, &bloom) {
+            bloom.finish(&settings.bloom);
+            camera::set_camera(&camera);
+        }
+        ui::draw_orbits(
+            &ui_state,
+            &camera,
+            &simulation_buffer,
+            settings.draw_settings.line_thickness,
+        );
+        ui::draw_selection(
+            &ui_state,
+            &camera,
+            &simulation_buffer,
+            settings.draw_settings.line_thickness,
+        );
+        ui::draw_brush(
+            &ui_state,
+            &camera,
+            &simulation_buffer,
+            settings.draw_settings.line_thickness,
+        );
 
         // Draw debug
         if debug_mode > 0 {
@@ -192,18 +455,114 @@ async fn main() {
 
             if let Some(tick_time) = tick_time {
                 let tps = (1.0 / tick_time.as_secs_f64()).round();
-                text::draw_text(&format!("TPS: {tps}"), 4.0, 50.0, 32.0, colors::WHITE);
+                let color = if falling_behind {
+                    colors::RED
+                } else {
+                    colors::WHITE
+                };
+                text::draw_text(&format!("TPS: {tps}"), 4.0, 50.0, 32.0, color);
+            }
+
+            let color = if simulation_buffer.is_crowded() {
+                colors::RED
+            } else {
+                colors::WHITE
+            };
+            text::draw_text(
+                &format!("Max bucket: {}", simulation_buffer.max_bucket_occupancy()),
+                4.0,
+                76.0,
+                32.0,
+                color,
+            );
+
+            text::draw_text(
+                &format!(
+                    "Collect: {:.2} ms, Sort: {:.2} ms ({} particles)",
+                    draw_stats.collect_time.as_secs_f64() * 1000.0,
+                    draw_stats.sort_time.as_secs_f64() * 1000.0,
+                    draw_stats.particles,
+                ),
+                4.0,
+                102.0,
+                32.0,
+                colors::WHITE,
+            );
+        }
+
+        if settings.show_scale_bar {
+            draw_scale_bar(&camera);
+        }
+
+        // Copy the view before the notice is drawn, so that an old notice isn't in the image
+        if key_pressed(KeyCode::F2) {
+            notice = Some((capture::copy_view_to_clipboard(), Instant::now()));
+        }
+        if let Some((message, shown)) = &notice {
+            if shown.elapsed() < NOTICE_DURATION {
+                camera::set_default_camera();
+                text::draw_text(
+                    message,
+                    10.0,
+                    window::screen_height() - 50.0,
+                    24.0,
+                    colors::WHITE,
+                );
+            } else {
+                notice = None;
             }
         }
 
+        // Limit the framerate, and lower it further while nothing is changing
+        if has_user_input() {
+            last_activity = Instant::now();
+        }
+        let idle = paused && last_activity.elapsed() > IDLE_DELAY;
+        let fps_limit = [
+            settings.fps_limit,
+            if idle { settings.idle_fps } else { 0.0 },
+        ]
+        .into_iter()
+        .filter(|&fps| fps > 0.0)
+        .reduce(f32::min);
+        if let Some(fps_limit) = fps_limit {
+            // Deadlines are spaced from the previous deadline in stead of from the start of the
+            // frame, so that time spent waiting for vsync doesn't lower the framerate further
+            let frame_time = Duration::from_secs_f32(1.0 / fps_limit);
+            frame_deadline = (frame_deadline + frame_time).max(frame_start);
+            thread::sleep(frame_deadline.saturating_duration_since(Instant::now()));
+        }
+
         window::next_frame().await;
     }
 }
 
-fn update_camera_control(camera: &mut Camera2D, pan_speed: f32, zoom_speed: f32) {
+/// How long a notice stays on the screen.
+const NOTICE_DURATION: Duration = Duration::from_secs(4);
+
+/// How long the app waits without any activity before lowering the framerate while paused.
+const IDLE_DELAY: Duration = Duration::from_secs(1);
+
+fn has_user_input() -> bool {
+    !input::get_keys_down().is_empty()
+        || input::mouse_wheel() != (0.0, 0.0)
+        || input::mouse_delta_position() != Vec2::ZERO
+        || [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
+            .into_iter()
+            .any(input::is_mouse_button_down)
+}
+
+/// Returns whether the camera was panned. The keys don't pan the camera while `typing`.
+fn update_camera_control(
+    camera: &mut Camera2D,
+    pan_speed: f32,
+    zoom_speed: f32,
+    typing: bool,
+) -> bool {
+    let key_down = |key| (!typing && input::is_key_down(key)) as u32 as f32;
     let motion = vec2(
-        input::is_key_down(KeyCode::D) as u32 as f32 - input::is_key_down(KeyCode::A) as u32 as f32,
-        input::is_key_down(KeyCode::S) as u32 as f32 - input::is_key_down(KeyCode::W) as u32 as f32,
+        key_down(KeyCode::D) - key_down(KeyCode::A),
+        key_down(KeyCode::S) - key_down(KeyCode::W),
     ) * (time::get_frame_time() * pan_speed / camera.zoom.y)
         * if input::is_key_down(KeyCode::LeftShift) {
             2.0
@@ -211,25 +570,142 @@ fn update_camera_control(camera: &mut Camera2D, pan_speed: f32, zoom_speed: f32)
             1.0
         };
 
-    let scroll = 1.0 + input::mouse_wheel().1 * zoom_speed;
+    // Scrolling resizes the brush in stead while it's active
+    let scroll = if ui::brush_active() {
+        1.0
+    } else {
+        1.0 + input::mouse_wheel().1 * zoom_speed
+    };
 
     camera.target += motion;
     camera.zoom *= scroll;
-}
 
-fn center_camera(camera: &mut Camera2D, size: Vec2) {
-    camera.target = size / 2.0;
-    camera.zoom = 2.0 / size;
+    motion != Vec2::ZERO
 }
 
-fn update_camera_aspect_ratio(camera: &mut Camera2D) {
-    camera.zoom.x = camera.zoom.y * window::screen_height() / window::screen_width();
+/// Draws a scale bar and the world position of the cursor in the bottom left corner of the
+/// screen. This leaves the default camera set.
+fn draw_scale_bar(camera: &Camera2D) {
+    camera::set_default_camera();
+    let pixels_per_unit = view::pixels_per_unit(camera);
+
+    // Pick a round length close to 150 pixels
+    let target_length = 150.0 / pixels_per_unit;
+    let magnitude = 10f32.powf(target_length.log10().floor());
+    let length = [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|x| x * magnitude)
+        .find(|&length| length <= target_length)
+        .unwrap_or(magnitude);
+    let bar_length = length * pixels_per_unit;
+
+    let left = 10.0;
+    let bottom = window::screen_height() - 10.0;
+    shapes::draw_line(left, bottom, left + bar_length, bottom, 2.0, colors::WHITE);
+    for x in [left, left + bar_length] {
+        shapes::draw_line(x, bottom - 8.0, x, bottom, 2.0, colors::WHITE);
+    }
+    let decimals = (-magnitude.log10()).max(0.0).round() as usize;
+    text::draw_text(
+        &format!("{length:.decimals$}"),
+        left + 4.0,
+        bottom - 8.0,
+        24.0,
+        colors::WHITE,
+    );
+
+    let cursor = view::cursor_position(camera);
+    text::draw_text(
+        &format!("({:.1}, {:.1})", cursor.x, cursor.y),
+        left,
+        bottom - 32.0,
+        24.0,
+        colors::WHITE,
+    );
 }
 
 pub struct SimulationThreadData {
     pub active: bool,
     pub reset: bool,
     pub tick_time: Option<Duration>,
+    /// Whether the last tick took longer than the time budget for one tick.
+    pub falling_behind: bool,
+    /// The number of buckets along each axis of the simulation created on reset.
+    pub grid_size: [usize; 2],
+    /// Changes to apply to the simulation on its next tick, even while paused.
+    pub edits: Vec<SimulationEdit>,
+    /// The number of times the simulation thread has updated the shared simulation.
+    pub update_count: u64,
+    /// Advanced once per step while the simulation is active.
+    pub morph: Option<AttractionMorph>,
+    /// Steps to take even while paused, one per tick.
+    pub steps: usize,
+    /// The tick rate to aim for, or 0 to run as fast as possible. This is ignored while
+    /// `time_dilation` is set.
+    pub tps_limit: f64,
+    /// Changes the tick rate with the kinetic energy of the particles when set.
+    pub time_dilation: Option<TimeDilation>,
+    /// Records a metric on every step. This is dropped if writing to the file fails.
+    pub recorder: Option<MetricRecorder>,
+    pub recording_error: Option<io::Error>,
+    /// Follows particles on every step to find the ones that are orbiting.
+    pub orbits: Option<OrbitDetector>,
+}
+
+/// Slows the tick rate down while particles move quickly and speeds it up while they move slowly,
+/// so that they move at about the same speed on screen. Only the tick rate changes, not the size
+/// of each step, so the simulation itself is unaffected.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeDilation {
+    /// The mean kinetic energy that runs at `TICK_RATE`.
+    pub reference_energy: f64,
+    pub min_tick_rate: f64,
+    pub max_tick_rate: f64,
+}
+
+impl Default for TimeDilation {
+    fn default() -> Self {
+        Self {
+            reference_energy: 0.5,
+            min_tick_rate: 5.0,
+            max_tick_rate: 120.0,
+        }
+    }
+}
+
+impl TimeDilation {
+    /// How fast the simulation is running compared to running at `TICK_RATE`, given how long a
+    /// tick took. This is what the dilation actually achieved, which is lower than asked for when
+    /// the simulation can't keep up.
+    pub fn effective_speed(tick_time: Duration) -> f64 {
+        if tick_time.is_zero() {
+            return 0.0;
+        }
+        1.0 / tick_time.as_secs_f64() / TICK_RATE
+    }
+
+    /// The tick rate for the given mean kinetic energy, which is inversely proportional to the
+    /// mean speed it corresponds to.
+    pub fn tick_rate(&self, energy: f64) -> f64 {
+        let min = self.min_tick_rate.max(1.0);
+        let max = self.max_tick_rate.max(min);
+        if energy <= 0.0 || self.reference_energy <= 0.0 {
+            return max;
+        }
+        (TICK_RATE * (self.reference_energy / energy).sqrt()).clamp(min, max)
+    }
+}
+
+pub type SimulationEdit = Box<dyn FnOnce(&mut ParticleSimulation) + Send>;
+
+impl SimulationThreadData {
+    /// Whether the simulation should take a step this tick. Uses up one of the `steps` left, if
+    /// there are any.
+    pub fn take_step(&mut self) -> bool {
+        let step = self.active || self.steps > 0;
+        self.steps = self.steps.saturating_sub(1);
+        step
+    }
 }
 
 impl Default for SimulationThreadData {
@@ -238,6 +714,61 @@ impl Default for SimulationThreadData {
             active: true,
             reset: false,
             tick_time: None,
+            falling_behind: false,
+            grid_size: DEFAULT_GRID_SIZE,
+            edits: Vec::new(),
+            update_count: 0,
+            morph: None,
+            steps: 0,
+            tps_limit: TICK_RATE,
+            time_dilation: None,
+            recorder: None,
+            recording_error: None,
+            orbits: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_advance_a_paused_simulation() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([3, 3])
+            .random_type_data(3, 1.0)
+            .random_particles(50)
+            .build();
+        let mut thread_data = SimulationThreadData {
+            active: false,
+            steps: 100,
+            ..Default::default()
+        };
+
+        // More ticks than steps, like the simulation thread keeps ticking while paused
+        for _ in 0..150 {
+            if thread_data.take_step() {
+                simulation.step_simulation();
+            }
+        }
+        assert_eq!(simulation.step_count(), 100);
+        assert_eq!(thread_data.steps, 0);
+    }
+
+    #[test]
+    fn time_dilation_speed() {
+        let dilation = TimeDilation::default();
+        assert_eq!(dilation.tick_rate(dilation.reference_energy), TICK_RATE);
+        assert_eq!(
+            dilation.tick_rate(dilation.reference_energy * 4.0),
+            TICK_RATE / 2.0
+        );
+        assert_eq!(dilation.tick_rate(1e9), dilation.min_tick_rate);
+        assert_eq!(dilation.tick_rate(0.0), dilation.max_tick_rate);
+
+        let speed = TimeDilation::effective_speed(Duration::from_secs_f64(2.0 / TICK_RATE));
+        assert!((speed - 0.5).abs() < 1e-6);
+        assert_eq!(TimeDilation::effective_speed(Duration::ZERO), 0.0);
+    }
+}
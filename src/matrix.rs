@@ -13,16 +13,18 @@ impl<T> Matrix<T> {
     {
         Self {
             size,
-            data: std::iter::repeat(element).take(size[0] * size[1]).collect(),
+            data: std::iter::repeat_n(element, size[0] * size[1]).collect(),
         }
     }
 
+    /// Calls `function` with every index in the order the elements are stored in, which is with
+    /// the first index changing fastest, since element `[i, j]` is stored at `i + j * size[0]`.
     pub fn from_fn<F>(size: [usize; 2], function: F) -> Self
     where
         F: FnMut([usize; 2]) -> T,
     {
-        let data = (0..size[0])
-            .flat_map(|i| (0..size[1]).map(move |j| [i, j]))
+        let data = (0..size[1])
+            .flat_map(|j| (0..size[0]).map(move |i| [i, j]))
             .map(function)
             .collect();
         Self { size, data }
@@ -60,3 +62,29 @@ impl<T> IndexMut<[usize; 2]> for Matrix<T> {
         self.get_mut(index).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_fn_agrees_with_index() {
+        let matrix = Matrix::from_fn([2, 3], |[i, j]| (i, j));
+        for i in 0..2 {
+            for j in 0..3 {
+                assert_eq!(matrix[[i, j]], (i, j));
+            }
+        }
+    }
+
+    #[test]
+    fn from_fn_calls_in_storage_order() {
+        let mut calls = Vec::new();
+        let matrix = Matrix::from_fn([2, 3], |index| {
+            calls.push(index);
+            calls.len() - 1
+        });
+        assert_eq!(&*matrix.data, &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(calls, [[0, 0], [1, 0], [0, 1], [1, 1], [0, 2], [1, 2]]);
+    }
+}
@@ -1,14 +1,33 @@
 use crate::matrix::Matrix;
+use builder::ParticleSimulationBuilder;
 use macroquad::{
     camera::Camera2D,
     color::{self, colors, Color},
+    material::{self, Material, MaterialParams},
     math::{vec2, Vec2},
+    miniquad::{BlendFactor, BlendState, BlendValue, Equation, PipelineParams, ShaderSource},
     shapes,
 };
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+pub mod analysis;
+pub mod builder;
+mod preset;
+pub mod scenario;
 
 pub const PARTICLE_RADIUS: f64 = 5.0;
 
+/// The default for `ParticleSimulation::max_particles`, well above what runs at a usable speed.
+pub const DEFAULT_MAX_PARTICLES: usize = 1_000_000;
+
+/// The largest subdivision that `ParticleSimulation::set_auto_subdivision` picks.
+pub const MAX_AUTO_SUBDIVISION: f64 = 8.0;
+
 #[rustfmt::skip]
 pub const NEIGHBORS: [[isize; 2]; 8] = [
     [-1, 1],  [0, 1],  [1, 1],
@@ -16,42 +35,735 @@ pub const NEIGHBORS: [[isize; 2]; 8] = [
     [-1, -1], [0, -1], [1, -1],
 ];
 
+/// A bucketed particle life simulation.
+///
+/// The simulation is advanced with `step_simulation` or `step_n`. For inspecting individual
+/// phases of a step (for example in tests), `move_particles` and `organize_particles` can be
+/// called directly, and the resulting state read back through `particles`.
 #[derive(Clone, Debug)]
 pub struct ParticleSimulation {
     buckets: Matrix<Vec<Particle>>,
     type_data: ParticleTypeData,
     bucket_size: f64,
     pub params: ParticleSimulationParams,
+    /// Used for spawning particles and separating particles at the same position, so that a given
+    /// seed always produces the same result.
+    rng: StdRng,
+    seed: u64,
+    /// See `type_cap`.
+    type_caps: Vec<Option<usize>>,
+    /// See `spawn_speed`.
+    spawn_speed: f64,
+    /// See `max_particles`.
+    max_particles: usize,
+    /// See `reset_velocity_on_retype`.
+    reset_velocity_on_retype: bool,
+    /// See `hit_particle_limit`.
+    hit_particle_limit: bool,
+    /// See `step_stats`.
+    step_stats: Option<StepStats>,
+    /// See `impulses`.
+    impulses: Option<HashMap<u64, [f64; 2]>>,
+    /// The id given to the next particle that is added.
+    next_id: u64,
+    /// See `step_count`.
+    step_count: u64,
+    /// See `interaction_range`.
+    interaction_range: f64,
+    /// See `subdivision`.
+    subdivision: f64,
+    /// See `auto_subdivision`.
+    auto_subdivision: Option<f64>,
+}
+
+/// The particles of a simulation without the bucket structure, for drawing and effects. Each
+/// list has one entry per particle, and the entries at the same index belong to the same
+/// particle.
+#[derive(Clone, Debug, Default)]
+pub struct RenderData {
+    pub positions: Vec<[f64; 2]>,
+    /// Particles move by half of their velocity each step (see `Particle::apply_velocity`).
+    pub velocities: Vec<[f64; 2]>,
+    /// Indices into the simulation's `ParticleTypeData`.
+    pub types: Vec<usize>,
+}
+
+/// Totals over all particles from one step of `move_particles`, see
+/// `ParticleSimulation::step_stats`.
+///
+/// To add a total, add a field, add to it for the bucket being worked on in `move_particles`,
+/// and combine it in `merge`. Each bucket's totals are kept apart until the step is done, so
+/// they never need to be shared between buckets.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StepStats {
+    /// The number of pairs of particles that were close enough to interact, counting each
+    /// direction separately.
+    pub interactions: usize,
+    /// The total kinetic energy of the particles once they have moved.
+    pub kinetic_energy: f64,
+}
+
+impl StepStats {
+    /// Combines the totals of two separate groups of particles.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            interactions: self.interactions + other.interactions,
+            kinetic_energy: self.kinetic_energy + other.kinetic_energy,
+        }
+    }
+}
+
+/// How long `draw_at` spent preparing the particles it drew, for finding out whether the
+/// preparation or the drawing itself is what slows frames down.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DrawStats {
+    /// The number of particles in the buckets that were on screen.
+    pub particles: usize,
+    /// The time spent collecting the particles of the buckets on screen. This includes drawing
+    /// the bucket overlay and edges, which happens in the same pass.
+    pub collect_time: Duration,
+    /// The time spent sorting the collected particles by type, which is 0 when they aren't
+    /// sorted.
+    pub sort_time: Duration,
+}
+
+/// What spawning keeps track of to stay within the type caps and `max_particles`.
+struct SpawnCounts {
+    /// The number of particles of each type, or `None` if no types have a cap.
+    types: Option<Vec<usize>>,
+    /// How many more particles can be added before reaching `max_particles`.
+    remaining: usize,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct ParticleSimulationParams {
     pub edge_type: EdgeType,
+    /// Changes the close range repulsion between particles less than `ejection_distance` apart.
+    /// The repulsion grows like `1 / distance` as particles get closer, which can fling nearly
+    /// coincident particles far apart in a single step. When this is set, such particles are
+    /// pulled together with a constant force in stead, and only repel once they are further
+    /// apart.
     pub prevent_particle_ejecting: bool,
+    /// The distance below which `prevent_particle_ejecting` takes over. The default of 1 is a
+    /// fifth of `PARTICLE_RADIUS`, where the repulsion is already strong enough to push a
+    /// particle 2.5 units in one step. Larger values group close particles into tighter
+    /// clumps, and values above `repulsion_distance` have no effect.
+    pub ejection_distance: f64,
+    /// The constant force between particles closer than `ejection_distance` when
+    /// `prevent_particle_ejecting` is set. This doesn't change any other forces. The default is
+    /// `PARTICLE_RADIUS`, which matches the repulsion between particles 1 apart.
+    pub overlap_strength: f64,
+    /// Particles closer than this repel each other in stead of following their attractions.
+    /// This is usually `2.0 * PARTICLE_RADIUS`, so that particles repel when they overlap.
+    pub repulsion_distance: f64,
+    /// When `false`, particles follow their attractions at every distance and can overlap
+    /// freely.
+    pub close_range_repulsion: bool,
+    /// Added to the squared distance between particles wherever the force divides by it, which
+    /// bounds the force between particles that are nearly on top of each other. 0 turns
+    /// softening off.
+    pub softening: f64,
+    pub coincident_particles: CoincidentParticles,
+    pub neighbor_search: NeighborSearch,
+    /// Pushes particles near the edges back towards the middle, on top of the edge type.
+    pub soft_boundary: Option<SoftBoundary>,
+}
+
+impl Default for ParticleSimulationParams {
+    fn default() -> Self {
+        Self {
+            edge_type: EdgeType::Bouncing {
+                multiplier: 1.0,
+                pushback: 2.5,
+            },
+            prevent_particle_ejecting: true,
+            ejection_distance: PARTICLE_RADIUS / 5.0,
+            overlap_strength: PARTICLE_RADIUS,
+            repulsion_distance: 2.0 * PARTICLE_RADIUS,
+            close_range_repulsion: true,
+            softening: 0.0,
+            coincident_particles: CoincidentParticles::default(),
+            neighbor_search: NeighborSearch::default(),
+            soft_boundary: None,
+        }
+    }
+}
+
+/// What happens to two particles at exactly the same position, where the direction between them
+/// is undefined.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CoincidentParticles {
+    /// Push them apart in a tiny direction picked from the seed.
+    #[default]
+    Nudge,
+    /// Leave them without any interaction between the two.
+    Skip,
+    /// Remove all but one of them at the end of the step. The interactions between them are
+    /// skipped until then.
+    Merge,
+}
+
+impl CoincidentParticles {
+    pub const ALL: [CoincidentParticles; 3] = [Self::Nudge, Self::Skip, Self::Merge];
+    pub const NAMES: [&'static str; 3] = ["Nudge", "Skip", "Merge"];
+}
+
+/// How `move_particles` finds the particles in other buckets that each particle interacts with.
+/// Particles never interact from further than the interaction range apart, so every strategy
+/// finds the same pairs, and they only differ in how many buckets are looked through to find them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NeighborSearch {
+    /// Look through the 8 buckets around the particle's bucket, or more rings of buckets when the
+    /// grid is subdivided.
+    #[default]
+    Neighbors,
+    /// Like `Neighbors`, but skip the corner buckets that are entirely out of reach of the
+    /// particle.
+    Radius,
+    /// Look through every other bucket, which checks every pair of particles. This is far too
+    /// slow for large simulations, but is a useful reference to check the grid against.
+    BruteForce,
+}
+
+impl NeighborSearch {
+    pub const ALL: [NeighborSearch; 3] = [Self::Neighbors, Self::Radius, Self::BruteForce];
+    pub const NAMES: [&'static str; 3] = ["Neighbors", "Radius", "Brute Force"];
+}
+
+/// What happens to particles of the types that are removed when the number of types is reduced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RemovedTypes {
+    /// Give each of them a random remaining type.
+    #[default]
+    Randomize,
+    /// Give them all the highest remaining type, which keeps the shape of any structures they
+    /// were part of.
+    Clamp,
+}
+
+impl RemovedTypes {
+    pub const ALL: [RemovedTypes; 2] = [Self::Randomize, Self::Clamp];
+    pub const NAMES: [&'static str; 2] = ["Randomize", "Clamp"];
+}
+
+/// Something wrong with the state given to `ParticleSimulation::try_with_particles`, which would
+/// make the simulation panic or misbehave later on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Violation {
+    /// The bucket size isn't positive and finite.
+    BucketSize(f64),
+    /// The grid has no buckets along one of the axes.
+    ZeroBuckets([usize; 2]),
+    /// A different number of particles was given than the state said there would be, for
+    /// example because a file was cut short.
+    ParticleCount { expected: usize, actual: usize },
+    /// The attraction matrix isn't square, or doesn't have one row for each color.
+    AttractionSize { size: [usize; 2], num_colors: usize },
+    /// The particle at `index` in the list has a type that isn't in the type data.
+    ParticleType { index: usize, typ: usize },
+    /// The particle at `index` in the list has a position or velocity that isn't finite.
+    NonFinite { index: usize },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::BucketSize(bucket_size) => {
+                write!(f, "Bucket size {bucket_size} isn't positive and finite")
+            }
+            Self::ZeroBuckets(buckets) => {
+                write!(f, "The grid is {} by {} buckets", buckets[0], buckets[1])
+            }
+            Self::ParticleCount { expected, actual } => {
+                write!(f, "Expected {expected} particles, but there are {actual}")
+            }
+            Self::AttractionSize { size, num_colors } => write!(
+                f,
+                "Attractions are {} by {}, but there are {num_colors} colors",
+                size[0], size[1]
+            ),
+            Self::ParticleType { index, typ } => {
+                write!(f, "Particle {index} has type {typ}, which doesn't exist")
+            }
+            Self::NonFinite { index } => {
+                write!(
+                    f,
+                    "Particle {index} has a position or velocity that isn't finite"
+                )
+            }
+        }
+    }
+}
+
+/// Every `Violation` found by `ParticleSimulation::validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidSimulation {
+    pub violations: Vec<Violation>,
+}
+
+impl std::fmt::Display for InvalidSimulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{violation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidSimulation {}
+
+/// A margin along the edges of the simulation that particles are pushed out of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoftBoundary {
+    /// How far the margin reaches in from each edge.
+    pub margin: f64,
+    /// The impulse applied each step to a particle right at an edge. Particles further in are
+    /// pushed less, down to nothing at the inside of the margin.
+    pub strength: f64,
+}
+
+impl Default for SoftBoundary {
+    fn default() -> Self {
+        Self {
+            margin: 50.0,
+            strength: 0.5,
+        }
+    }
+}
+
+impl SoftBoundary {
+    /// Returns the impulse on a particle at `position` in a simulation of the given size.
+    pub fn impulse(&self, position: [f64; 2], size: [f64; 2]) -> [f64; 2] {
+        if self.margin <= 0.0 {
+            return [0.0; 2];
+        }
+        [0, 1].map(|axis| {
+            let low = (self.margin - position[axis]).max(0.0);
+            let high = (self.margin - (size[axis] - position[axis])).max(0.0);
+            (low - high).clamp(-self.margin, self.margin) / self.margin * self.strength
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DrawSettings {
+    pub draw_border: bool,
+    pub border_color: Color,
+    /// Multiplies the thickness of the border, on top of `line_thickness`.
+    pub border_thickness: f32,
+    pub border_style: BorderStyle,
+    /// Draws a square on each corner of the border, with a larger one on the corner at the
+    /// origin, so the orientation of the world can be told apart.
+    pub border_corner_markers: bool,
+    pub draw_bucket_edges: bool,
+    pub bucket_edge_color: Color,
+    /// Draws faint lines across the whole view, lined up with the edges of the buckets.
+    pub draw_background_grid: bool,
+    /// The number of background grid cells across each bucket.
+    pub background_grid_divisions: u32,
+    /// The alpha of this sets how faint the background grid is.
+    pub background_grid_color: Color,
+    /// Whether the debug view outlines the interaction range of the particle under the cursor.
+    pub draw_interaction_range: bool,
+    /// Multiplies the thickness of the border, bucket edges, and other lines.
+    pub line_thickness: f32,
+    /// Draws an arrow over each bucket showing the average velocity of its particles.
+    pub draw_flow_field: bool,
+    /// The length of a flow field arrow per unit of velocity.
+    pub flow_field_scale: f32,
+    /// Draws a line from each particle along its velocity.
+    pub draw_velocities: bool,
+    /// The length of a velocity line per unit of velocity.
+    pub velocity_scale: f32,
+    /// Draws a line from each particle along the impulse it got in the last step, if the
+    /// simulation records impulses (see `ParticleSimulation::impulses`).
+    pub draw_impulses: bool,
+    /// The length of an impulse line per unit of impulse.
+    pub impulse_scale: f32,
+    pub bucket_overlay: BucketOverlay,
+    pub particle_shape: ParticleShape,
+    /// Draws particles grouped by type, so that particles of higher types are drawn on top of
+    /// lower ones. Otherwise they are drawn in the order of their buckets. Either way every
+    /// particle is drawn with the same material, so this only changes which particles overlap
+    /// which.
+    pub sort_particles_by_type: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BorderStyle {
+    #[default]
+    Solid,
+    Dashed,
+    /// Dashed when the edges wrap, and solid otherwise.
+    Auto,
+}
+
+impl BorderStyle {
+    pub const ALL: [BorderStyle; 3] = [Self::Solid, Self::Dashed, Self::Auto];
+    pub const NAMES: [&'static str; 3] = ["Solid", "Dashed", "Auto"];
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParticleShape {
+    #[default]
+    Circle,
+    Square,
+    Triangle,
+    Diamond,
+}
+
+impl ParticleShape {
+    pub const ALL: [ParticleShape; 4] = [Self::Circle, Self::Square, Self::Triangle, Self::Diamond];
+    pub const NAMES: [&'static str; 4] = ["Circle", "Square", "Triangle", "Diamond"];
+
+    pub fn draw(self, position: Vec2, radius: f32, color: Color) {
+        match self {
+            Self::Circle => shapes::draw_circle(position.x, position.y, radius, color),
+            Self::Square => shapes::draw_rectangle(
+                position.x - radius,
+                position.y - radius,
+                radius * 2.0,
+                radius * 2.0,
+                color,
+            ),
+            Self::Triangle => shapes::draw_poly(position.x, position.y, 3, radius, -90.0, color),
+            Self::Diamond => shapes::draw_poly(position.x, position.y, 4, radius, 0.0, color),
+        }
+    }
+}
+
+/// A value shaded over each bucket, relative to the bucket where it is highest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BucketOverlay {
+    #[default]
+    None,
+    /// The number of particles in the bucket.
+    Density,
+    /// The number of particles in the bucket squared, approximating the number of interactions
+    /// it is responsible for.
+    Load,
+}
+
+impl BucketOverlay {
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Density,
+            Self::Density => Self::Load,
+            Self::Load => Self::None,
+        }
+    }
+
+    fn value_of(self, bucket: &[Particle]) -> f32 {
+        match self {
+            Self::None => 0.0,
+            Self::Density => bucket.len() as f32,
+            Self::Load => (bucket.len() as f32).powi(2),
+        }
+    }
+}
+
+impl Default for DrawSettings {
+    fn default() -> Self {
+        Self {
+            draw_border: true,
+            border_color: colors::GRAY,
+            border_thickness: 1.0,
+            border_style: BorderStyle::Solid,
+            border_corner_markers: false,
+            draw_bucket_edges: false,
+            draw_interaction_range: true,
+            bucket_edge_color: colors::DARKGRAY,
+            draw_background_grid: false,
+            background_grid_divisions: 1,
+            background_grid_color: Color::new(1.0, 1.0, 1.0, 0.1),
+            line_thickness: 1.0,
+            draw_flow_field: false,
+            flow_field_scale: 10.0,
+            draw_velocities: false,
+            velocity_scale: 5.0,
+            draw_impulses: false,
+            impulse_scale: 20.0,
+            bucket_overlay: BucketOverlay::None,
+            particle_shape: ParticleShape::Circle,
+            sort_particles_by_type: true,
+        }
+    }
 }
 
 impl ParticleSimulation {
+    /// Starts setting up a simulation from the defaults listed on `ParticleSimulationBuilder`.
+    pub fn builder() -> ParticleSimulationBuilder {
+        ParticleSimulationBuilder::default()
+    }
+
+    /// Panics if `bucket_size` isn't positive and finite.
     pub fn new(
         bucket_size: f64,
         buckets: [usize; 2],
         params: ParticleSimulationParams,
         num_types: usize,
-        attraction_intensity: f64,
+        attraction_scale: f64,
     ) -> Self {
-        Self {
+        Self::with_particles(
+            bucket_size,
+            buckets,
+            params,
+            ParticleTypeData::new_random(num_types, attraction_scale),
+            Vec::new(),
+        )
+    }
+
+    /// Creates a simulation containing exactly the given particles. Particles outside of the
+    /// simulation are handled according to `params.edge_type`, so they may be moved inside or
+    /// dropped.
+    ///
+    /// Panics if `bucket_size` isn't positive and finite.
+    pub fn with_particles(
+        bucket_size: f64,
+        buckets: [usize; 2],
+        params: ParticleSimulationParams,
+        type_data: ParticleTypeData,
+        particles: Vec<Particle>,
+    ) -> Self {
+        assert!(
+            bucket_size.is_finite() && bucket_size > 0.0,
+            "bucket size must be positive and finite, but was {bucket_size}"
+        );
+        let seed = rand::thread_rng().gen();
+        let mut simulation = Self {
             buckets: Matrix::from_element(buckets, Vec::new()),
-            type_data: ParticleTypeData::new_random(num_types, attraction_intensity),
+            type_data,
+            bucket_size,
+            params,
+            rng: StdRng::seed_from_u64(seed),
+            seed,
+            type_caps: Vec::new(),
+            spawn_speed: 0.0,
+            max_particles: DEFAULT_MAX_PARTICLES,
+            reset_velocity_on_retype: false,
+            hit_particle_limit: false,
+            step_stats: None,
+            impulses: None,
+            next_id: 0,
+            step_count: 0,
+            interaction_range: bucket_size,
+            subdivision: 1.0,
+            auto_subdivision: None,
+        };
+        for mut particle in particles {
+            particle.id = simulation.new_id();
+            simulation.insert_particle_with_edge_type(particle);
+        }
+        simulation
+    }
+
+    /// Like `with_particles`, but checks the state with `validate` first, for state that came
+    /// from somewhere that can't be trusted, like a file. `expected_particles` is the number of
+    /// particles the source said it had, if it said.
+    pub fn try_with_particles(
+        bucket_size: f64,
+        buckets: [usize; 2],
+        params: ParticleSimulationParams,
+        type_data: ParticleTypeData,
+        particles: Vec<Particle>,
+        expected_particles: Option<usize>,
+    ) -> Result<Self, InvalidSimulation> {
+        Self::validate(
             bucket_size,
+            buckets,
+            &type_data,
+            &particles,
+            expected_particles,
+        )?;
+        Ok(Self::with_particles(
+            bucket_size,
+            buckets,
             params,
+            type_data,
+            particles,
+        ))
+    }
+
+    /// Checks everything that `with_particles` relies on without creating the simulation, and
+    /// returns every problem found in stead of stopping at the first one.
+    pub fn validate(
+        bucket_size: f64,
+        buckets: [usize; 2],
+        type_data: &ParticleTypeData,
+        particles: &[Particle],
+        expected_particles: Option<usize>,
+    ) -> Result<(), InvalidSimulation> {
+        let mut violations = Vec::new();
+        if !(bucket_size.is_finite() && bucket_size > 0.0) {
+            violations.push(Violation::BucketSize(bucket_size));
+        }
+        if buckets.contains(&0) {
+            violations.push(Violation::ZeroBuckets(buckets));
+        }
+        if let Some(expected) = expected_particles.filter(|&count| count != particles.len()) {
+            violations.push(Violation::ParticleCount {
+                expected,
+                actual: particles.len(),
+            });
+        }
+        let size = type_data.base_attractions.size;
+        let num_colors = type_data.colors.len();
+        if size[0] != size[1] || size[0] != num_colors {
+            violations.push(Violation::AttractionSize { size, num_colors });
+        }
+        for (index, particle) in particles.iter().enumerate() {
+            if particle.typ >= type_data.num_types() {
+                violations.push(Violation::ParticleType {
+                    index,
+                    typ: particle.typ,
+                });
+            }
+            if !particle
+                .position
+                .iter()
+                .chain(&particle.velocity)
+                .all(|x| x.is_finite())
+            {
+                violations.push(Violation::NonFinite { index });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidSimulation { violations })
+        }
+    }
+
+    /// Changes the number of buckets along each axis, keeping the bucket size. Particles that
+    /// end up outside of the simulation are handled according to `params.edge_type`.
+    pub fn resize_grid(&mut self, buckets: [usize; 2]) {
+        let old_buckets =
+            std::mem::replace(&mut self.buckets, Matrix::from_element(buckets, Vec::new()));
+        for particle in old_buckets.data.into_vec().into_iter().flatten() {
+            self.insert_particle_with_edge_type(particle);
+        }
+    }
+
+    pub fn grid_size(&self) -> [usize; 2] {
+        self.buckets.size
+    }
+
+    /// Changes the number of types, keeping the attractions and colors of the remaining types.
+    /// Particles of removed types are given new types according to `removed`, or are all removed
+    /// if there are no types left.
+    pub fn set_num_types(&mut self, num_types: usize, removed: RemovedTypes) {
+        if num_types == self.type_data.num_types() {
+            return;
+        }
+        self.type_data.resize(num_types);
+        self.type_caps.truncate(num_types);
+        if num_types == 0 {
+            self.clear_particles();
+            return;
+        }
+
+        for particle in self.buckets.data.iter_mut().flatten() {
+            if particle.typ >= num_types {
+                particle.typ = match removed {
+                    RemovedTypes::Randomize => self.rng.gen_range(0..num_types),
+                    RemovedTypes::Clamp => num_types - 1,
+                };
+                if self.reset_velocity_on_retype {
+                    particle.velocity = [0.0, 0.0];
+                }
+            }
         }
     }
 
+    /// Advances the simulation by one step. This is equivalent to calling `move_particles`
+    /// followed by `organize_particles`.
     pub fn step_simulation(&mut self) {
+        self.move_particles();
+        self.organize_particles();
+        self.step_count += 1;
+        if let Some(max_occupancy) = self.auto_subdivision {
+            self.auto_subdivide(max_occupancy);
+        }
+    }
+
+    /// Advances the simulation by `n` steps.
+    pub fn step_n(&mut self, n: usize) {
+        for _ in 0..n {
+            self.step_simulation();
+        }
+    }
+
+    /// The number of times `step_simulation` has been called since the simulation was created.
+    /// Calling `move_particles` or `organize_particles` on their own doesn't count as a step.
+    /// Clones keep the count, and so does resizing the grid, but a newly built simulation starts
+    /// from 0.
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    /// Applies the interactions between particles to their velocities, then moves each particle.
+    ///
+    /// Particles are not moved between buckets, so this leaves the simulation in an unorganized
+    /// state until `organize_particles` is called.
+    pub fn move_particles(&mut self) {
         // (the unsafe blocks that cast a reference to a raw pointer and back are to skip the
         // borrow checker)
 
-        // Cache the rng (is used when particles have 0 distance)
-        let mut rng = rand::thread_rng();
+        // Particles with 0 distance are pushed apart in a direction picked from this and the
+        // pair's indices, so the result doesn't depend on the order pairs are visited in
+        let step_seed: u64 = self.rng.gen();
+        let bucket_width = self.buckets.size[0];
+        let id_of = |bucket_index: [usize; 2], index: usize| {
+            (((bucket_index[0] + bucket_index[1] * bucket_width) as u64) << 32) | index as u64
+        };
+
+        // Particles at the same position as an earlier particle in their bucket, to be removed
+        let merge = self.params.coincident_particles == CoincidentParticles::Merge;
+        let mut merged = Vec::new();
+
+        // Stats are gathered separately for each bucket and only merged once every bucket is
+        // done, so that no bucket's work writes to anything shared. A parallel loop over the
+        // buckets could gather them the same way, and merging in bucket order keeps the float
+        // sums the same however the work is split up.
+        let counting = self.step_stats.is_some();
+        let mut bucket_stats = if counting {
+            vec![StepStats::default(); self.buckets.data.len()]
+        } else {
+            Vec::new()
+        };
+
+        // The velocities before any forces are applied, to work out the impulses from
+        if let Some(impulses) = &mut self.impulses {
+            impulses.clear();
+        }
+        let start_velocities: Option<HashMap<u64, [f64; 2]>> = self.impulses.is_some().then(|| {
+            self.particles()
+                .map(|particle| (particle.id, particle.velocity))
+                .collect()
+        });
+
+        // The other buckets that the particles in the current bucket might interact with
+        let mut neighbor_buckets = Vec::new();
+        let neighbor_offsets = self.neighbor_offsets();
+        let bucket_size = self.bucket_size;
+        let interaction_range = self.interaction_range;
+        let out_of_reach = |position: [f64; 2], bucket_index: [usize; 2]| {
+            let distance_squared: f64 = (0..2)
+                .map(|axis| {
+                    let min = bucket_index[axis] as f64 * bucket_size;
+                    let max = min + bucket_size;
+                    (min - position[axis])
+                        .max(position[axis] - max)
+                        .max(0.0)
+                        .powi(2)
+                })
+                .sum();
+            distance_squared > interaction_range.powi(2)
+        };
 
         // Update particle velocity
         for bucket_x in 0..self.buckets.size[0] {
@@ -69,65 +781,150 @@ impl ParticleSimulation {
                     let particle = unsafe { ((&mut bucket[i]) as *mut Particle).as_mut().unwrap() };
 
                     // Iterate over each index up to but not including i
-                    for j in 0..i {
-                        particle.update_with_particle(
-                            bucket[j],
+                    for (j, other) in bucket[..i].iter_mut().enumerate() {
+                        if merge && particle.position == other.position {
+                            merged.push((bucket_index, i));
+                            break;
+                        }
+
+                        let separation = || {
+                            separation_vector(
+                                step_seed,
+                                id_of(bucket_index, j),
+                                id_of(bucket_index, i),
+                            )
+                        };
+                        let interacted = particle.update_with_particle(
+                            *other,
                             &self.type_data,
                             &self.params,
-                            self.bucket_size,
-                            &mut rng,
+                            interaction_range,
+                            separation,
                         );
-                        bucket[j].update_with_particle(
+                        let other_interacted = other.update_with_particle(
                             *particle,
                             &self.type_data,
                             &self.params,
-                            self.bucket_size,
-                            &mut rng,
+                            interaction_range,
+                            || separation().map(|x| -x),
                         );
+                        if counting {
+                            bucket_stats[bucket_x + bucket_y * bucket_width].interactions +=
+                                interacted as usize + other_interacted as usize;
+                        }
                     }
                 }
 
                 // Update from neighboring buckets
-                for i in 0..bucket.len() {
-                    // Safety: particle is never accessed from this bucket again
-                    let particle = unsafe { ((&mut bucket[i]) as *mut Particle).as_mut().unwrap() };
-                    for bucket_relative_index in NEIGHBORS {
-                        let neighbor_bucket_index = {
-                            let index = [
-                                bucket_index[0].checked_add_signed(bucket_relative_index[0]),
-                                bucket_index[1].checked_add_signed(bucket_relative_index[1]),
-                            ];
-                            if index.contains(&None) {
-                                continue;
+                neighbor_buckets.clear();
+                neighbor_buckets.extend(neighbor_offsets.iter().filter_map(|relative_index| {
+                    let index = [
+                        bucket_index[0].checked_add_signed(relative_index[0])?,
+                        bucket_index[1].checked_add_signed(relative_index[1])?,
+                    ];
+                    self.buckets.get(index).map(|_| index)
+                }));
+                if self.params.neighbor_search == NeighborSearch::BruteForce {
+                    // The neighbors come first, in the same order as the grid search, so that
+                    // forces are added up in the same order and both give the same result
+                    let num_neighbors = neighbor_buckets.len();
+                    for x in 0..self.buckets.size[0] {
+                        for y in 0..self.buckets.size[1] {
+                            let index = [x, y];
+                            if index != bucket_index
+                                && !neighbor_buckets[..num_neighbors].contains(&index)
+                            {
+                                neighbor_buckets.push(index);
                             }
-                            // Safety: Just checked if bucket_index contains None
-                            index.map(|x| x.unwrap())
-                        };
+                        }
+                    }
+                }
+                let radius = self.params.neighbor_search == NeighborSearch::Radius;
+                for (i, particle) in bucket.iter_mut().enumerate() {
+                    for &neighbor_bucket_index in &neighbor_buckets {
+                        if radius && out_of_reach(particle.position, neighbor_bucket_index) {
+                            continue;
+                        }
 
-                        if let Some(neighbor_bucket) = self.buckets.get(neighbor_bucket_index) {
-                            for &other in neighbor_bucket {
-                                particle.update_with_particle(
-                                    other,
-                                    &self.type_data,
-                                    &self.params,
-                                    self.bucket_size,
-                                    &mut rng,
-                                );
+                        for (j, &other) in self.buckets[neighbor_bucket_index].iter().enumerate() {
+                            let id = id_of(bucket_index, i);
+                            let other_id = id_of(neighbor_bucket_index, j);
+                            let interacted = particle.update_with_particle(
+                                other,
+                                &self.type_data,
+                                &self.params,
+                                interaction_range,
+                                || {
+                                    // Both particles of the pair get the same direction, but
+                                    // facing opposite ways
+                                    let vector = separation_vector(
+                                        step_seed,
+                                        id.min(other_id),
+                                        id.max(other_id),
+                                    );
+                                    if id < other_id {
+                                        vector.map(|x| -x)
+                                    } else {
+                                        vector
+                                    }
+                                },
+                            );
+                            if counting {
+                                bucket_stats[bucket_x + bucket_y * bucket_width].interactions +=
+                                    interacted as usize;
                             }
                         }
                     }
                 }
             }
         }
+        // Remove merged particles, starting from the end of each bucket so that the remaining
+        // indices stay valid
+        for &(bucket_index, i) in merged.iter().rev() {
+            self.buckets[bucket_index].swap_remove(i);
+        }
 
         // Move particles
-        for bucket in self.buckets.data.iter_mut() {
+        let size = self.size();
+        for (bucket_index, bucket) in self.buckets.data.iter_mut().enumerate() {
             for particle in bucket {
+                if let Some(soft_boundary) = &self.params.soft_boundary {
+                    let impulse = soft_boundary.impulse(particle.position, size);
+                    particle.velocity[0] += impulse[0];
+                    particle.velocity[1] += impulse[1];
+                }
+                if let (Some(impulses), Some(start_velocities)) =
+                    (&mut self.impulses, &start_velocities)
+                {
+                    let start = start_velocities[&particle.id];
+                    impulses.insert(
+                        particle.id,
+                        [
+                            particle.velocity[0] - start[0],
+                            particle.velocity[1] - start[1],
+                        ],
+                    );
+                }
                 particle.apply_velocity();
+                if counting {
+                    bucket_stats[bucket_index].kinetic_energy +=
+                        (particle.velocity[0].powi(2) + particle.velocity[1].powi(2)) / 2.0;
+                }
             }
         }
 
-        // Organize particles
+        if counting {
+            self.step_stats = Some(
+                bucket_stats
+                    .into_iter()
+                    .fold(StepStats::default(), StepStats::merge),
+            );
+        }
+    }
+
+    /// Moves each particle into the bucket matching its position, applying the edge type to any
+    /// particles outside of the simulation.
+    pub fn organize_particles(&mut self) {
         for bucket_x in 0..self.buckets.size[0] {
             for bucket_y in 0..self.buckets.size[1] {
                 let bucket_index = [bucket_x, bucket_y];
@@ -142,40 +939,9 @@ impl ParticleSimulation {
                 while i < bucket.len() {
                     let particle = &mut bucket[i];
 
-                    let index = self.bucket_index_of_position(particle.position);
-                    if index != Some(bucket_index) {
-                        if index.is_some() {
-                            self.insert_particle(*particle);
-                        } else {
-                            // Edge handling
-                            match self.params.edge_type {
-                                EdgeType::Wrapping => {
-                                    let size = self.size();
-                                    particle.position[0] = particle.position[0].rem_euclid(size[0]);
-                                    particle.position[1] = particle.position[1].rem_euclid(size[1]);
-                                    self.insert_particle(*particle);
-                                }
-                                EdgeType::Bouncing {
-                                    multiplier,
-                                    pushback,
-                                } => {
-                                    let direction = particle.constrain_to_size(self.size());
-                                    if direction[0] != 0.0 {
-                                        particle.velocity[0] =
-                                            (particle.velocity[0].abs() * multiplier + pushback)
-                                                * direction[0];
-                                    }
-                                    if direction[1] != 0.0 {
-                                        particle.velocity[1] =
-                                            (particle.velocity[1].abs() * multiplier + pushback)
-                                                * direction[1];
-                                    }
-                                    self.insert_particle(*particle);
-                                }
-                                EdgeType::Deleting => (),
-                            }
-                        }
-                        bucket.swap_remove(i);
+                    if self.bucket_index_of_position(particle.position) != Some(bucket_index) {
+                        let particle = bucket.swap_remove(i);
+                        self.insert_particle_with_edge_type(particle);
                     } else {
                         i += 1;
                     }
@@ -184,26 +950,70 @@ impl ParticleSimulation {
         }
     }
 
-    pub fn draw_at(&self, position: Vec2, camera: &Camera2D, draw_debug_graphics: bool) {
+    /// Inserts a particle, applying the edge type first if it is outside of the simulation. This
+    /// is the only place particles that leave the simulation are removed.
+    fn insert_particle_with_edge_type(&mut self, mut particle: Particle) {
+        if self.bucket_index_of_position(particle.position).is_some()
+            || self.apply_edge_type(&mut particle)
+        {
+            self.place_particle(particle);
+        }
+    }
+
+    /// Moves a particle outside of the simulation back inside according to the edge type.
+    /// Returns `false` if the particle should be removed in stead.
+    fn apply_edge_type(&self, particle: &mut Particle) -> bool {
+        match self.params.edge_type {
+            EdgeType::Wrapping => {
+                let size = self.size();
+                particle.position[0] = particle.position[0].rem_euclid(size[0]);
+                particle.position[1] = particle.position[1].rem_euclid(size[1]);
+                true
+            }
+            EdgeType::Bouncing {
+                multiplier,
+                pushback,
+            } => {
+                let direction = particle.constrain_to_size(self.size());
+                if direction[0] != 0.0 {
+                    particle.velocity[0] =
+                        (particle.velocity[0].abs() * multiplier + pushback) * direction[0];
+                }
+                if direction[1] != 0.0 {
+                    particle.velocity[1] =
+                        (particle.velocity[1].abs() * multiplier + pushback) * direction[1];
+                }
+                true
+            }
+            EdgeType::Deleting => false,
+        }
+    }
+
+    pub fn draw_at(&self, position: Vec2, camera: &Camera2D, settings: &DrawSettings) -> DrawStats {
         // Draw border
-        let radius = (0.005 / camera.zoom[1]).max(2.0);
-        let offset = radius / 2.0 + PARTICLE_RADIUS as f32;
-        let size = self.size();
-        shapes::draw_rectangle_lines(
-            position.x as f32 - offset,
-            position.y as f32 - offset,
-            size[0] as f32 + offset * 2.0,
-            size[1] as f32 + offset * 2.0,
-            radius,
-            colors::GRAY,
-        );
+        let radius = (0.005 / camera.zoom[1]).max(2.0) * settings.line_thickness;
+        if settings.draw_background_grid {
+            self.draw_background_grid(position, camera, radius * 0.5, settings);
+        }
+        if settings.draw_border {
+            self.draw_border(position, radius * settings.border_thickness, settings);
+        }
 
         let min_corner =
             camera.target - 1.0 / camera.zoom - (PARTICLE_RADIUS + self.bucket_size) as f32;
         let max_corner = camera.target + 1.0 / camera.zoom + PARTICLE_RADIUS as f32;
 
+        let max_overlay_value = self
+            .buckets
+            .data
+            .iter()
+            .map(|bucket| settings.bucket_overlay.value_of(bucket))
+            .fold(0.0, f32::max);
+
         // Collect particles
+        let collect_start = Instant::now();
         let mut particles = Vec::new();
+        let mut flow_arrows = Vec::new();
         for bucket_x in 0..self.buckets.size[0] {
             for bucket_y in 0..self.buckets.size[1] {
                 let bucket_index = [bucket_x, bucket_y];
@@ -220,15 +1030,27 @@ impl ParticleSimulation {
 
                 let bucket = &self.buckets[bucket_index];
 
+                // Draw bucket overlay
+                if max_overlay_value > 0.0 {
+                    let value = settings.bucket_overlay.value_of(bucket) / max_overlay_value;
+                    shapes::draw_rectangle(
+                        bucket_position.x + position.x,
+                        bucket_position.y + position.y,
+                        self.bucket_size as f32,
+                        self.bucket_size as f32,
+                        Color::new(1.0, 0.2, 0.0, value * 0.6),
+                    );
+                }
+
                 // Draw chunk debug
-                if draw_debug_graphics {
+                if settings.draw_bucket_edges {
                     shapes::draw_rectangle_lines(
                         bucket_position.x,
                         bucket_position.y,
                         self.bucket_size as f32,
                         self.bucket_size as f32,
                         radius,
-                        colors::DARKGRAY,
+                        settings.bucket_edge_color,
                     );
                 }
 
@@ -236,9 +1058,99 @@ impl ParticleSimulation {
                 for particle in bucket {
                     particles.push(particle);
                 }
+
+                // Sample the average velocity of the bucket
+                if settings.draw_flow_field && !bucket.is_empty() {
+                    let mut velocity = [0.0; 2];
+                    for particle in bucket {
+                        velocity[0] += particle.velocity[0];
+                        velocity[1] += particle.velocity[1];
+                    }
+                    let velocity = velocity.map(|x| x / bucket.len() as f64);
+                    let center = bucket_position + position + self.bucket_size as f32 / 2.0;
+                    flow_arrows.push((center, vec2(velocity[0] as f32, velocity[1] as f32)));
+                }
+            }
+        }
+
+        let collect_time = collect_start.elapsed();
+
+        let sort_start = Instant::now();
+        let num_particles = particles.len();
+        let particles_sorted = if settings.sort_particles_by_type {
+            self.sort_by_type(particles)
+        } else {
+            particles
+        };
+        let sort_time = if settings.sort_particles_by_type {
+            sort_start.elapsed()
+        } else {
+            Duration::ZERO
+        };
+
+        // Draw particles
+        PREMULTIPLIED_MATERIAL.with(|material| {
+            material::gl_use_material(material.get_or_init(load_premultiplied_material))
+        });
+        for &particle in particles_sorted.iter() {
+            let position = [
+                particle.position[0] + position.x as f64,
+                particle.position[1] + position.y as f64,
+            ];
+            let color = premultiplied(self.type_data.colors[particle.typ]);
+            settings.particle_shape.draw(
+                vec2(position[0] as f32, position[1] as f32),
+                PARTICLE_RADIUS as f32,
+                color,
+            );
+        }
+        material::gl_use_default_material();
+
+        // Draw velocities
+        if settings.draw_velocities {
+            for particle in particles_sorted.iter() {
+                let start =
+                    vec2(particle.position[0] as f32, particle.position[1] as f32) + position;
+                let end = start
+                    + vec2(particle.velocity[0] as f32, particle.velocity[1] as f32)
+                        * settings.velocity_scale;
+                shapes::draw_line(start.x, start.y, end.x, end.y, radius / 2.0, colors::WHITE);
+            }
+        }
+
+        // Draw impulses
+        if let Some(impulses) = self.impulses.as_ref().filter(|_| settings.draw_impulses) {
+            for particle in particles_sorted.iter() {
+                let Some(impulse) = impulses.get(&particle.id) else {
+                    continue;
+                };
+                let start =
+                    vec2(particle.position[0] as f32, particle.position[1] as f32) + position;
+                let end =
+                    start + vec2(impulse[0] as f32, impulse[1] as f32) * settings.impulse_scale;
+                shapes::draw_line(start.x, start.y, end.x, end.y, radius / 2.0, colors::ORANGE);
             }
         }
 
+        // Draw flow field
+        for (center, velocity) in flow_arrows {
+            Self::draw_arrow(
+                center,
+                velocity * settings.flow_field_scale,
+                radius,
+                colors::WHITE,
+            );
+        }
+
+        DrawStats {
+            particles: num_particles,
+            collect_time,
+            sort_time,
+        }
+    }
+
+    /// Orders particles by type, keeping the order of particles of the same type.
+    fn sort_by_type<'a>(&self, particles: Vec<&'a Particle>) -> Vec<&'a Particle> {
         // Sort particles (counting sort):
         // counting step
         let mut indecies: Box<[usize]> = (0..self.type_data.num_types()).map(|_| 0).collect();
@@ -255,31 +1167,143 @@ impl ParticleSimulation {
         }
 
         // filling step
-        let mut particles_sorted: Box<[_]> =
-            (0..particles.len()).map(|_| Particle::default()).collect();
+        let mut particles_sorted = particles.clone();
         for particle in particles {
-            particles_sorted[indecies[particle.typ]] = *particle;
+            particles_sorted[indecies[particle.typ]] = particle;
             indecies[particle.typ] += 1;
         }
-
-        // Draw particles
-        for &particle in particles_sorted.iter() {
-            let position = [
-                particle.position[0] + position.x as f64,
-                particle.position[1] + position.y as f64,
-            ];
-            let color = self.type_data.colors[particle.typ];
-            shapes::draw_circle(
-                position[0] as f32,
-                position[1] as f32,
-                PARTICLE_RADIUS as f32,
-                color,
-            );
-        }
+        particles_sorted
     }
 
-    pub fn size(&self) -> [f64; 2] {
-        self.buckets.size.map(|x| x as f64 * self.bucket_size)
+    /// Outlines the area that `particle` interacts with, using the bucket edge color.
+    pub fn draw_interaction_range(
+        &self,
+        position: Vec2,
+        camera: &Camera2D,
+        particle: &Particle,
+        settings: &DrawSettings,
+    ) {
+        let thickness = (0.005 / camera.zoom[1]).max(2.0) * settings.line_thickness;
+        shapes::draw_circle_lines(
+            particle.position[0] as f32 + position.x,
+            particle.position[1] as f32 + position.y,
+            self.interaction_range() as f32,
+            thickness,
+            settings.bucket_edge_color,
+        );
+    }
+
+    fn draw_arrow(start: Vec2, delta: Vec2, thickness: f32, color: Color) {
+        let end = start + delta;
+        shapes::draw_line(start.x, start.y, end.x, end.y, thickness, color);
+
+        // Arrow head
+        let head = delta * 0.25;
+        for side in [head.perp(), -head.perp()] {
+            let tip = end - head + side * 0.5;
+            shapes::draw_line(end.x, end.y, tip.x, tip.y, thickness, color);
+        }
+    }
+
+    /// Draws grid lines over everything the camera can see, including outside of the world.
+    fn draw_background_grid(
+        &self,
+        position: Vec2,
+        camera: &Camera2D,
+        thickness: f32,
+        settings: &DrawSettings,
+    ) {
+        const MAX_LINES: f32 = 500.0;
+
+        let corners = [
+            camera.target - 1.0 / camera.zoom,
+            camera.target + 1.0 / camera.zoom,
+        ];
+        let min = corners[0].min(corners[1]);
+        let max = corners[0].max(corners[1]);
+
+        // When zoomed far out, skip every other line until there are few enough to draw. The
+        // remaining lines still fall on bucket edges.
+        let mut spacing =
+            self.bucket_size as f32 / settings.background_grid_divisions.max(1) as f32;
+        while ((max - min) / spacing).max_element() > MAX_LINES {
+            spacing *= 2.0;
+        }
+
+        let color = settings.background_grid_color;
+        let first = ((min - position) / spacing).floor();
+        let last = ((max - position) / spacing).ceil();
+        for i in first.x as i32..=last.x as i32 {
+            let x = position.x + i as f32 * spacing;
+            shapes::draw_line(x, min.y, x, max.y, thickness, color);
+        }
+        for i in first.y as i32..=last.y as i32 {
+            let y = position.y + i as f32 * spacing;
+            shapes::draw_line(min.x, y, max.x, y, thickness, color);
+        }
+    }
+
+    fn draw_border(&self, position: Vec2, thickness: f32, settings: &DrawSettings) {
+        let offset = thickness / 2.0 + PARTICLE_RADIUS as f32;
+        let size = self.size();
+        let min = position - offset;
+        let max = position + vec2(size[0] as f32, size[1] as f32) + offset;
+        let color = settings.border_color;
+
+        let dashed = match settings.border_style {
+            BorderStyle::Solid => false,
+            BorderStyle::Dashed => true,
+            BorderStyle::Auto => matches!(self.params.edge_type, EdgeType::Wrapping),
+        };
+        if dashed {
+            let corners = [min, vec2(max.x, min.y), max, vec2(min.x, max.y)];
+            for i in 0..4 {
+                draw_dashed_line(corners[i], corners[(i + 1) % 4], thickness, color);
+            }
+        } else {
+            let size = max - min;
+            shapes::draw_rectangle_lines(min.x, min.y, size.x, size.y, thickness, color);
+        }
+
+        if settings.border_corner_markers {
+            let marker_size = thickness * 6.0;
+            for (corner, scale) in [
+                (min, 2.0),
+                (vec2(max.x, min.y), 1.0),
+                (max, 1.0),
+                (vec2(min.x, max.y), 1.0),
+            ] {
+                let half_size = marker_size * scale / 2.0;
+                shapes::draw_rectangle(
+                    corner.x - half_size,
+                    corner.y - half_size,
+                    half_size * 2.0,
+                    half_size * 2.0,
+                    color,
+                );
+            }
+        }
+    }
+
+    pub fn bucket_size(&self) -> f64 {
+        self.bucket_size
+    }
+
+    pub fn size(&self) -> [f64; 2] {
+        self.buckets.size.map(|x| x as f64 * self.bucket_size)
+    }
+
+    /// Returns the displacement from `a` to `b`. With wrapping edges this is the shortest
+    /// displacement across the edges of the simulation, so points near opposite edges are close.
+    pub fn delta_wrapped(&self, a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+        let size = self.size();
+        let mut delta = [b[0] - a[0], b[1] - a[1]];
+        if let EdgeType::Wrapping = self.params.edge_type {
+            for (delta, size) in delta.iter_mut().zip(size) {
+                *delta -= (*delta / size).round() * size;
+            }
+        }
+        delta
     }
 
     pub fn size_vec2(&self) -> Vec2 {
@@ -287,24 +1311,545 @@ impl ParticleSimulation {
         vec2(size[0] as f32, size[1] as f32)
     }
 
+    /// The corners of the simulation with the smallest and largest coordinates.
+    pub fn world_bounds(&self) -> (Vec2, Vec2) {
+        (Vec2::ZERO, self.size_vec2())
+    }
+
+    pub fn type_data(&self) -> &ParticleTypeData {
+        &self.type_data
+    }
+
+    pub fn type_data_mut(&mut self) -> &mut ParticleTypeData {
+        &mut self.type_data
+    }
+
+    /// Checks whether the current particles and grid could be used with `type_data` in stead of
+    /// the current type data, the same way `try_with_particles` does, for type data that came
+    /// from somewhere that can't be trusted.
+    pub fn validate_type_data(
+        &self,
+        type_data: &ParticleTypeData,
+    ) -> Result<(), InvalidSimulation> {
+        let particles: Vec<Particle> = self.particles().copied().collect();
+        Self::validate(
+            self.bucket_size,
+            self.buckets.size,
+            type_data,
+            &particles,
+            None,
+        )
+    }
+
+    pub fn particles(&self) -> impl Iterator<Item = &Particle> {
+        self.buckets.data.iter().flatten()
+    }
+
+    /// Copies the state of every particle into flat lists, in the same order as `particles`.
+    pub fn render_data(&self) -> RenderData {
+        let count = self.num_particles();
+        let mut data = RenderData {
+            positions: Vec::with_capacity(count),
+            velocities: Vec::with_capacity(count),
+            types: Vec::with_capacity(count),
+        };
+        for particle in self.particles() {
+            data.positions.push(particle.position);
+            data.velocities.push(particle.velocity);
+            data.types.push(particle.typ);
+        }
+        data
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.buckets.data.iter().map(Vec::len).sum()
+    }
+
+    /// Particles only affect each other when they are closer than this. This is the bucket size
+    /// the simulation was created with, and doesn't change when the grid is subdivided.
+    pub fn interaction_range(&self) -> f64 {
+        self.interaction_range
+    }
+
+    /// How many buckets fit across the interaction range along each axis. This is 1 unless the
+    /// grid has been subdivided.
+    pub fn subdivision(&self) -> f64 {
+        self.subdivision
+    }
+
+    /// Splits the grid into buckets `1 / factor` times the interaction range across. Smaller
+    /// buckets hold fewer particles, so fewer pairs that are out of range are checked in crowded
+    /// simulations, but `ceil(factor)` rings of buckets have to be searched around each bucket in
+    /// stead of 1. Interactions are unaffected. Factors below 1 are treated as 1.
+    ///
+    /// If the simulation isn't a whole number of the new buckets across, the grid is rounded up
+    /// to the next whole bucket, so the simulation grows slightly.
+    pub fn set_subdivision(&mut self, factor: f64) {
+        let factor = if factor.is_finite() {
+            factor.max(1.0)
+        } else {
+            1.0
+        };
+        if factor == self.subdivision {
+            return;
+        }
+        let size = self.size();
+        self.subdivision = factor;
+        self.bucket_size = self.interaction_range / factor;
+        // (the small offset keeps rounding errors from adding a bucket)
+        let buckets = size.map(|x| ((x / self.bucket_size - 1e-9).ceil() as usize).max(1));
+        self.resize_grid(buckets);
+    }
+
+    /// The mean number of particles per bucket above which `step_simulation` subdivides the grid
+    /// further, or `None` if the subdivision only changes through `set_subdivision`.
+    pub fn auto_subdivision(&self) -> Option<f64> {
+        self.auto_subdivision
+    }
+
+    /// While this is set, `step_simulation` keeps the subdivision at the smallest whole factor
+    /// (up to `MAX_AUTO_SUBDIVISION`) that keeps the mean bucket occupancy at or below
+    /// `max_occupancy`.
+    pub fn set_auto_subdivision(&mut self, max_occupancy: Option<f64>) {
+        self.auto_subdivision = max_occupancy.map(|x| x.max(1.0));
+    }
+
+    fn auto_subdivide(&mut self, max_occupancy: f64) {
+        let size = self.size();
+        // The mean occupancy of buckets as large as the interaction range, which falls with the
+        // square of the subdivision
+        let occupancy =
+            self.num_particles() as f64 * self.interaction_range.powi(2) / (size[0] * size[1]);
+        let factor = (occupancy / max_occupancy)
+            .sqrt()
+            .ceil()
+            .clamp(1.0, MAX_AUTO_SUBDIVISION);
+        self.set_subdivision(factor);
+    }
+
+    /// The positions of the other buckets that may hold particles within the interaction range
+    /// of a bucket's particles, relative to that bucket.
+    fn neighbor_offsets(&self) -> Vec<[isize; 2]> {
+        let rings = self.subdivision.ceil() as isize;
+        if rings <= 1 {
+            return NEIGHBORS.to_vec();
+        }
+        (-rings..=rings)
+            .flat_map(|x| (-rings..=rings).map(move |y| [x, y]))
+            .filter(|&offset| offset != [0, 0])
+            .collect()
+    }
+
+    /// Returns the particle closest to `position` that is within `interaction_range` of it.
+    pub fn nearest_particle(&self, position: [f64; 2]) -> Option<&Particle> {
+        let bucket_index = position.map(|x| (x / self.bucket_size).floor() as isize);
+        let mut nearest = None;
+        let mut nearest_distance_squared = self.interaction_range().powi(2);
+        for relative_index in std::iter::once([0, 0]).chain(self.neighbor_offsets()) {
+            let index = [
+                bucket_index[0] + relative_index[0],
+                bucket_index[1] + relative_index[1],
+            ];
+            if index.iter().any(|&x| x < 0) {
+                continue;
+            }
+            let Some(bucket) = self.buckets.get(index.map(|x| x as usize)) else {
+                continue;
+            };
+            for particle in bucket {
+                let distance_squared = (particle.position[0] - position[0]).powi(2)
+                    + (particle.position[1] - position[1]).powi(2);
+                if distance_squared < nearest_distance_squared {
+                    nearest = Some(particle);
+                    nearest_distance_squared = distance_squared;
+                }
+            }
+        }
+        nearest
+    }
+
+    /// Returns the average position of all particles, or `None` if there are no particles.
+    pub fn center_of_mass(&self) -> Option<[f64; 2]> {
+        let mut sum = [0.0; 2];
+        let mut count = 0;
+        for particle in self.particles() {
+            sum[0] += particle.position[0];
+            sum[1] += particle.position[1];
+            count += 1;
+        }
+        if count == 0 {
+            return None;
+        }
+        Some(sum.map(|x| x / count as f64))
+    }
+
+    /// Adds a particle, giving it a new id. Returns `None` without changing anything if it is
+    /// outside of the simulation or the simulation already has `max_particles` particles.
     pub fn insert_particle(&mut self, particle: Particle) -> Option<()> {
+        self.hit_particle_limit = self.num_particles() >= self.max_particles;
+        if self.hit_particle_limit {
+            return None;
+        }
+        self.insert_new_particle(particle)
+    }
+
+    /// Like `insert_particle`, but without checking `max_particles`, which takes a pass over
+    /// every bucket.
+    fn insert_new_particle(&mut self, mut particle: Particle) -> Option<()> {
+        self.bucket_index_of_position(particle.position)?;
+        particle.id = self.new_id();
+        self.place_particle(particle)
+    }
+
+    /// Puts a particle in the bucket at its position, keeping its id.
+    fn place_particle(&mut self, particle: Particle) -> Option<()> {
         let index = self.bucket_index_of_position(particle.position)?;
         self.buckets.get_mut(index)?.push(particle);
         Some(())
     }
 
+    fn new_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Calls `edit` on every particle, removing the ones it returns `false` for, then moves
+    /// particles into the buckets at their new positions. Particles with types that are out of
+    /// range are removed as well.
+    pub fn edit_particles(&mut self, mut edit: impl FnMut(&mut Particle) -> bool) {
+        let num_types = self.type_data.num_types();
+        let reset_velocity = self.reset_velocity_on_retype;
+        for bucket in self.buckets.data.iter_mut() {
+            bucket.retain_mut(|particle| {
+                let old_type = particle.typ;
+                let keep = edit(particle);
+                if reset_velocity && particle.typ != old_type {
+                    particle.velocity = [0.0, 0.0];
+                }
+                keep && particle.typ < num_types
+            });
+        }
+        self.organize_particles();
+    }
+
+    pub fn clear_particles(&mut self) {
+        for bucket in self.buckets.data.iter_mut() {
+            bucket.clear();
+        }
+    }
+
+    /// The number of particles needed to fill the simulation to the given density (in particles
+    /// per square unit).
+    pub fn count_for_density(&self, density: f64) -> usize {
+        let size = self.size();
+        (size[0] * size[1] * density) as usize
+    }
+
+    /// Adds random particles until the simulation reaches the given density.
+    pub fn fill_to_density(&mut self, density: f64) {
+        let count = self
+            .count_for_density(density)
+            .saturating_sub(self.num_particles());
+        self.add_random_particles(count);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the rng used for spawning and stepping from the given seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = seed;
+    }
+
     pub fn add_random_particles(&mut self, count: usize) {
-        let mut rng = rand::thread_rng();
+        let mut counts = self.spawn_counts();
         let size = self.size();
         for _ in 0..count {
-            let position = [rng.gen_range(0.0..size[0]), rng.gen_range(0.0..size[1])];
-            let particle = Particle::new(
-                position,
-                [0.0, 0.0],
-                rng.gen_range(0..self.type_data.num_types()),
-            );
-            self.insert_particle(particle);
+            let position = [
+                self.rng.gen_range(0.0..size[0]),
+                self.rng.gen_range(0.0..size[1]),
+            ];
+            let particle = Particle::new(position, [0.0, 0.0], self.random_type());
+            self.spawn_particle(particle, &mut counts);
+        }
+    }
+
+    /// Adds `count` particles spread uniformly over a circle. Particles that would fall outside
+    /// of the simulation are not added.
+    pub fn add_particles_in_circle(&mut self, center: [f64; 2], radius: f64, count: usize) {
+        self.add_particles_in_ring(center, 0.0, radius, count);
+    }
+
+    /// Adds `count` particles spread uniformly over the area between two circles. Particles that
+    /// would fall outside of the simulation are not added.
+    pub fn add_particles_in_ring(
+        &mut self,
+        center: [f64; 2],
+        inner_radius: f64,
+        outer_radius: f64,
+        count: usize,
+    ) {
+        self.add_ring_of_type(center, inner_radius, outer_radius, count, None);
+    }
+
+    /// Like `add_particles_in_circle`, but every particle is of type `typ`. Nothing is added if
+    /// `typ` isn't one of the simulation's types.
+    pub fn add_typed_particles_in_circle(
+        &mut self,
+        center: [f64; 2],
+        radius: f64,
+        count: usize,
+        typ: usize,
+    ) {
+        if typ < self.type_data.num_types() {
+            self.add_ring_of_type(center, 0.0, radius, count, Some(typ));
+        }
+    }
+
+    /// Adds particles of type `typ`, or of random types if it's `None`.
+    fn add_ring_of_type(
+        &mut self,
+        center: [f64; 2],
+        inner_radius: f64,
+        outer_radius: f64,
+        count: usize,
+        typ: Option<usize>,
+    ) {
+        let mut counts = self.spawn_counts();
+        for _ in 0..count {
+            // Taking the square root keeps the density even across the radius
+            let distance = self
+                .rng
+                .gen_range(inner_radius.powi(2)..=outer_radius.powi(2))
+                .sqrt();
+            let angle = self.rng.gen_range(0.0..std::f64::consts::TAU);
+            let position = [
+                center[0] + angle.cos() * distance,
+                center[1] + angle.sin() * distance,
+            ];
+            let typ = typ.unwrap_or_else(|| self.random_type());
+            let particle = Particle::new(position, [0.0, 0.0], typ);
+            self.spawn_particle(particle, &mut counts);
+        }
+    }
+
+    /// Adds up to `count` particles at random positions at least `min_distance` away from every
+    /// other particle, which spreads them out more evenly than `add_random_particles`. Gives up
+    /// on placing more particles after too many positions are rejected in a row, and returns how
+    /// many particles were added.
+    ///
+    /// `min_distance` is limited to the interaction range, since only neighboring buckets are
+    /// searched for particles that are too close.
+    pub fn add_particles_poisson(&mut self, count: usize, min_distance: f64) -> usize {
+        const MAX_ATTEMPTS: usize = 30;
+
+        let min_distance = min_distance.min(self.interaction_range());
+        let mut counts = self.spawn_counts();
+        let size = self.size();
+        let mut added = 0;
+        let mut attempts = 0;
+        while added < count && attempts < MAX_ATTEMPTS {
+            let position = [
+                self.rng.gen_range(0.0..size[0]),
+                self.rng.gen_range(0.0..size[1]),
+            ];
+            let too_close = self.nearest_particle(position).is_some_and(|other| {
+                (other.position[0] - position[0]).powi(2)
+                    + (other.position[1] - position[1]).powi(2)
+                    < min_distance.powi(2)
+            });
+            if too_close {
+                attempts += 1;
+                continue;
+            }
+
+            let typ = self.random_type();
+            if self.spawn_particle(Particle::new(position, [0.0, 0.0], typ), &mut counts) {
+                added += 1;
+                attempts = 0;
+            } else {
+                attempts += 1;
+            }
+        }
+        added
+    }
+
+    /// Fills the simulation with particles placed on a square grid with the given spacing.
+    pub fn add_particles_in_grid(&mut self, spacing: f64) {
+        let mut type_counts = self.spawn_counts();
+        let size = self.size();
+        let counts = size.map(|x| (x / spacing).floor() as usize);
+        for x in 0..counts[0] {
+            for y in 0..counts[1] {
+                let position = [(x as f64 + 0.5) * spacing, (y as f64 + 0.5) * spacing];
+                let particle = Particle::new(position, [0.0, 0.0], self.random_type());
+                self.spawn_particle(particle, &mut type_counts);
+            }
+        }
+    }
+
+    /// Adds `per_type` particles of each type, with each type gathered in a cluster around a
+    /// random center. The distance of particles from their cluster's center follows a normal
+    /// distribution with a standard deviation of `spread`. Particles that would fall outside of
+    /// the simulation are not added.
+    pub fn add_clustered_particles(&mut self, per_type: usize, spread: f64) {
+        let mut counts = self.spawn_counts();
+        let size = self.size();
+        for typ in 0..self.type_data.num_types() {
+            let center = [
+                self.rng.gen_range(0.0..size[0]),
+                self.rng.gen_range(0.0..size[1]),
+            ];
+            for _ in 0..per_type {
+                // Box-Muller transform
+                let radius = (-2.0 * (1.0 - self.rng.gen::<f64>()).ln()).sqrt() * spread;
+                let angle = self.rng.gen_range(0.0..std::f64::consts::TAU);
+                let position = [
+                    center[0] + angle.cos() * radius,
+                    center[1] + angle.sin() * radius,
+                ];
+                self.spawn_particle(Particle::new(position, [0.0, 0.0], typ), &mut counts);
+            }
+        }
+    }
+
+    /// The most particles of a type that spawning will add up to, or `None` if there's no limit.
+    /// Particles that are inserted directly aren't limited.
+    pub fn type_cap(&self, typ: usize) -> Option<usize> {
+        self.type_caps.get(typ).copied().flatten()
+    }
+
+    pub fn set_type_cap(&mut self, typ: usize, cap: Option<usize>) {
+        if self.type_caps.len() <= typ {
+            self.type_caps.resize(typ + 1, None);
+        }
+        self.type_caps[typ] = cap;
+    }
+
+    /// The largest speed given to spawned particles. Each spawned particle moves in a random
+    /// direction at a random speed up to this, or starts still if it's 0. Particles that are
+    /// inserted directly keep their velocity.
+    pub fn spawn_speed(&self) -> f64 {
+        self.spawn_speed
+    }
+
+    /// Negative speeds are treated as 0.
+    pub fn set_spawn_speed(&mut self, speed: f64) {
+        self.spawn_speed = speed.max(0.0);
+    }
+
+    /// Whether particles stop moving when their type is changed by `edit_particles` or
+    /// `set_num_types`. Otherwise they keep their velocity.
+    pub fn reset_velocity_on_retype(&self) -> bool {
+        self.reset_velocity_on_retype
+    }
+
+    pub fn set_reset_velocity_on_retype(&mut self, reset_velocity: bool) {
+        self.reset_velocity_on_retype = reset_velocity;
+    }
+
+    /// The most particles that spawning and `insert_particle` will add up to. This guards
+    /// against running out of memory, for example by holding down the brush. Particles passed to
+    /// `with_particles` aren't limited.
+    pub fn max_particles(&self) -> usize {
+        self.max_particles
+    }
+
+    /// Lowering the limit below the current number of particles doesn't remove any, but stops
+    /// more from being added.
+    pub fn set_max_particles(&mut self, max_particles: usize) {
+        self.max_particles = max_particles;
+    }
+
+    /// Whether the last spawn or `insert_particle` left particles out because the simulation
+    /// had `max_particles` particles.
+    pub fn hit_particle_limit(&self) -> bool {
+        self.hit_particle_limit
+    }
+
+    /// The number of pairs of particles that were close enough to interact during the last step,
+    /// counting each direction separately. This is `None` unless counting has been turned on
+    /// with `set_count_interactions`.
+    pub fn interaction_count(&self) -> Option<usize> {
+        self.step_stats.map(|stats| stats.interactions)
+    }
+
+    /// Totals gathered during the last step, or `None` unless counting has been turned on with
+    /// `set_count_interactions`.
+    pub fn step_stats(&self) -> Option<StepStats> {
+        self.step_stats
+    }
+
+    /// Turns gathering `step_stats`, which include the interaction count, on or off. The stats
+    /// are all 0 until the next step.
+    pub fn set_count_interactions(&mut self, count_interactions: bool) {
+        self.step_stats = count_interactions.then(StepStats::default);
+    }
+
+    /// The change in velocity of each particle from the forces of the last step, by id, before
+    /// friction. This is `None` unless recording has been turned on with
+    /// `set_record_impulses`, since it makes every step and every clone of the simulation
+    /// slower.
+    pub fn impulses(&self) -> Option<&HashMap<u64, [f64; 2]>> {
+        self.impulses.as_ref()
+    }
+
+    /// Turns recording impulses on or off. There are no impulses until the next step.
+    pub fn set_record_impulses(&mut self, record_impulses: bool) {
+        self.impulses = record_impulses.then(HashMap::new);
+    }
+
+    /// The particle counts `spawn_particle` needs. This starts a new spawn, so it also clears
+    /// `hit_particle_limit`.
+    fn spawn_counts(&mut self) -> SpawnCounts {
+        self.hit_particle_limit = false;
+        SpawnCounts {
+            types: self
+                .type_caps
+                .iter()
+                .any(Option::is_some)
+                .then(|| self.type_counts()),
+            remaining: self.max_particles.saturating_sub(self.num_particles()),
+        }
+    }
+
+    /// Inserts a spawned particle unless its type is at its cap or the simulation is full.
+    /// Returns whether the particle was added.
+    fn spawn_particle(&mut self, mut particle: Particle, counts: &mut SpawnCounts) -> bool {
+        if counts.remaining == 0 {
+            self.hit_particle_limit = true;
+            return false;
+        }
+        // Only drawing from the rng when needed keeps seeds giving the same particles as before
+        // spawn speeds existed
+        if self.spawn_speed > 0.0 {
+            let speed = self.rng.gen_range(0.0..=self.spawn_speed);
+            let angle = self.rng.gen_range(0.0..std::f64::consts::TAU);
+            particle.velocity = [angle.cos() * speed, angle.sin() * speed];
+        }
+        if let Some(type_counts) = &mut counts.types {
+            if self
+                .type_cap(particle.typ)
+                .is_some_and(|cap| type_counts[particle.typ] >= cap)
+            {
+                return false;
+            }
         }
+        if self.insert_new_particle(particle).is_none() {
+            return false;
+        }
+        if let Some(type_counts) = &mut counts.types {
+            type_counts[particle.typ] += 1;
+        }
+        counts.remaining -= 1;
+        true
+    }
+
+    fn random_type(&mut self) -> usize {
+        self.rng.gen_range(0..self.type_data.num_types())
     }
 
     fn position_of_bucket(&self, index: [usize; 2]) -> [f64; 2] {
@@ -329,22 +1874,54 @@ impl ParticleSimulation {
     }
 }
 
+/// Returns a tiny nonzero vector that only depends on its arguments, used as the offset between
+/// two particles at the same position. `first` and `second` identify the pair of particles.
+#[cold]
+fn separation_vector(seed: u64, first: u64, second: u64) -> [f64; 2] {
+    // SplitMix64
+    fn mix(mut x: u64) -> u64 {
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+        x ^ (x >> 31)
+    }
+
+    let hash = mix(mix(mix(seed) ^ first) ^ second);
+    // Map each half of the hash to -0.1..=0.1
+    let component = |bits: u64| (bits as f64 / u32::MAX as f64 - 0.5) * 0.2;
+    let vector = [component(hash >> 32), component(hash & u32::MAX as u64)];
+    if vector == [0.0, 0.0] {
+        [0.1, 0.0]
+    } else {
+        vector
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Particle {
     pub position: [f64; 2],
     pub velocity: [f64; 2],
     pub typ: usize,
+    /// Given by the simulation when the particle is added, see `id`.
+    id: u64,
 }
 
 impl Particle {
+    /// Creates a particle with an id of 0. The simulation gives it a new id when it is added.
     pub fn new(position: [f64; 2], velocity: [f64; 2], typ: usize) -> Self {
         Self {
             position,
             velocity,
             typ,
+            id: 0,
         }
     }
 
+    /// Identifies the particle within its simulation, staying the same as it moves between
+    /// buckets. Ids aren't reused, even after a particle is removed.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     pub fn apply_velocity(&mut self) {
         self.position[0] += self.velocity[0] / 2.0;
         self.position[1] += self.velocity[1] / 2.0;
@@ -352,47 +1929,48 @@ impl Particle {
         self.velocity = self.velocity.map(|x| x * 0.9);
     }
 
+    /// Applies the effect of `other` to this particle's velocity. Returns whether they were close
+    /// enough to interact.
     pub fn update_with_particle(
         &mut self,
         other: Particle,
         type_data: &ParticleTypeData,
         params: &ParticleSimulationParams,
         max_distance: f64,
-        rng: &mut ThreadRng,
-    ) {
-        #[cold]
-        fn randomize_vector(delta_position: &mut [f64; 2], rng: &mut ThreadRng) {
-            delta_position[0] = rng.gen_range(-0.1..=0.1);
-            delta_position[1] = rng.gen_range(-0.1..=0.1);
-        }
-
+        separation: impl FnOnce() -> [f64; 2],
+    ) -> bool {
         let mut delta_position = [
             other.position[0] - self.position[0],
             other.position[1] - self.position[1],
         ];
-        // Prevent division by 0 (this has an astronomically low chance to block for some time)
-        while delta_position == [0.0, 0.0] {
-            randomize_vector(&mut delta_position, rng);
+        // Prevent division by 0
+        if delta_position == [0.0, 0.0] {
+            match params.coincident_particles {
+                CoincidentParticles::Nudge => delta_position = separation(),
+                CoincidentParticles::Skip | CoincidentParticles::Merge => return false,
+            }
         }
 
         let distance_squared = delta_position[0].powi(2) + delta_position[1].powi(2);
         if distance_squared > max_distance.powi(2) {
-            return;
+            return false;
         }
 
-        let distance_squared = distance_squared;
-
+        let softened = distance_squared + params.softening;
         let attraction;
-        if distance_squared > PARTICLE_RADIUS.powi(2) * 4.0 {
-            attraction = type_data.get_attraction(self.typ, other.typ) / distance_squared;
-        } else if params.prevent_particle_ejecting && distance_squared < 1.0 {
-            attraction = PARTICLE_RADIUS / distance_squared.sqrt();
+        if !params.close_range_repulsion || distance_squared > params.repulsion_distance.powi(2) {
+            attraction = type_data.get_attraction(self.typ, other.typ) / softened;
+        } else if params.prevent_particle_ejecting
+            && distance_squared < params.ejection_distance.powi(2)
+        {
+            attraction = params.overlap_strength / softened.sqrt();
         } else {
-            attraction = -PARTICLE_RADIUS / distance_squared;
+            attraction = -PARTICLE_RADIUS / softened;
         }
 
         self.velocity[0] += attraction * delta_position[0];
         self.velocity[1] += attraction * delta_position[1];
+        true
     }
 
     pub fn constrain_to_size(&mut self, size: [f64; 2]) -> [f64; 2] {
@@ -429,28 +2007,1752 @@ pub enum EdgeType {
 
 #[derive(Clone, Debug)]
 pub struct ParticleTypeData {
-    types: Matrix<f64>,
+    /// The attractions between types before they are multiplied by `positive_scale` or
+    /// `negative_scale`.
+    base_attractions: Matrix<f64>,
+    /// Multiplies base attractions that are at least 0.
+    positive_scale: f64,
+    /// Multiplies base attractions below 0, which are repulsions.
+    negative_scale: f64,
+    /// Random base attractions are chosen between `-random_range` and `random_range`.
+    random_range: f64,
     colors: Box<[Color]>,
 }
 
 impl ParticleTypeData {
-    pub fn new_random(num_types: usize, attraction_intensity: f64) -> Self {
-        let mut rng = rand::thread_rng();
-        let types = Matrix::from_fn([num_types; 2], |_| {
-            rng.gen_range(-attraction_intensity..=attraction_intensity)
-        });
+    /// Creates type data with base attractions chosen randomly between -1 and 1.
+    pub fn new_random(num_types: usize, attraction_scale: f64) -> Self {
+        Self::new_random_in_range(num_types, attraction_scale, 1.0)
+    }
+
+    /// Like `new_random`, but with base attractions between `-range` and `range`, which is kept
+    /// as the `random_range`.
+    pub fn new_random_in_range(num_types: usize, attraction_scale: f64, range: f64) -> Self {
+        let mut type_data = Self::new_from_fn(num_types, attraction_scale, |_| 0.0);
+        type_data.set_random_range(range);
+        type_data.randomize_attractions();
+        type_data
+    }
+
+    /// Creates attractions that fall from 1 between particles of the same type towards -1
+    /// between types far apart on the color wheel. `falloff` is the distance in types over which
+    /// the attraction (shifted to be between 0 and 2) falls by a factor of e.
+    pub fn new_gradient(num_types: usize, attraction_scale: f64, falloff: f64) -> Self {
+        Self::new_from_fn(num_types, attraction_scale, |[source, target]| {
+            // Types wrap around like the hues of their colors
+            let difference = source.abs_diff(target);
+            let distance = difference.min(num_types - difference) as f64;
+            2.0 * (-distance / falloff).exp() - 1.0
+        })
+    }
+
+    /// Creates type data where the base attraction of `source` to `target` is given by
+    /// `attraction_fn([source, target])`.
+    pub fn new_from_fn<F>(num_types: usize, attraction_scale: f64, attraction_fn: F) -> Self
+    where
+        F: FnMut([usize; 2]) -> f64,
+    {
         let colors = (0..num_types)
             .map(|typ| typ as f32 / num_types as f32)
             .map(|hue| color::hsl_to_rgb(hue, 1.0, 0.5))
             .collect();
-        Self { types, colors }
+        Self::new_with_colors(num_types, attraction_scale, attraction_fn, colors).unwrap()
+    }
+
+    /// Like `new_from_fn`, but with one color for each type in stead of colors spread around the
+    /// color wheel. Returns `None` if the number of colors doesn't match `num_types`.
+    pub fn new_with_colors<F>(
+        num_types: usize,
+        attraction_scale: f64,
+        attraction_fn: F,
+        colors: Box<[Color]>,
+    ) -> Option<Self>
+    where
+        F: FnMut([usize; 2]) -> f64,
+    {
+        if colors.len() != num_types {
+            return None;
+        }
+        Some(Self {
+            base_attractions: Matrix::from_fn([num_types; 2], attraction_fn),
+            positive_scale: attraction_scale,
+            negative_scale: attraction_scale,
+            random_range: 1.0,
+            colors,
+        })
     }
 
+    // The scale is applied on every read in stead of being cached in a second matrix. This costs
+    // one multiply and a branch per interaction, which doesn't measurably change the time per
+    // step, and means changing the scales is free.
     pub fn get_attraction(&self, source: usize, target: usize) -> f64 {
-        self.types[[source, target]]
+        let base_attraction = self.base_attractions[[source, target]];
+        if base_attraction >= 0.0 {
+            base_attraction * self.positive_scale
+        } else {
+            base_attraction * self.negative_scale
+        }
     }
 
-    pub fn num_types(&self) -> usize {
-        self.types.size[0]
+    pub fn get_base_attraction(&self, source: usize, target: usize) -> f64 {
+        self.base_attractions[[source, target]]
+    }
+
+    /// Sets the base attraction of `source` to `target`, so `get_attraction` returns `value`
+    /// multiplied by the attraction scale. Returns `None` if either type is out of range.
+    pub fn set_attraction(&mut self, source: usize, target: usize, value: f64) -> Option<()> {
+        *self.base_attractions.get_mut([source, target])? = value;
+        Some(())
+    }
+
+    /// Negates every attraction, turning attractions into repulsions and vice versa.
+    pub fn invert(&mut self) {
+        for attraction in self.base_attractions.data.iter_mut() {
+            *attraction = -*attraction;
+        }
+    }
+
+    /// Makes each attraction the opposite of the attraction in the other direction, by averaging
+    /// it with the negated opposite attraction. If type A is attracted to type B, B is repelled
+    /// by A just as much, so A chases B. Self-attractions become 0.
+    pub fn antisymmetrize(&mut self) {
+        for source in 0..self.num_types() {
+            for target in source..self.num_types() {
+                let value = (self.base_attractions[[source, target]]
+                    - self.base_attractions[[target, source]])
+                    / 2.0;
+                self.base_attractions[[source, target]] = value;
+                self.base_attractions[[target, source]] = -value;
+            }
+        }
+    }
+
+    /// Multiplies the attraction of each type to itself, leaving attractions between different
+    /// types unchanged.
+    pub fn scale_diagonal(&mut self, factor: f64) {
+        for typ in 0..self.num_types() {
+            self.base_attractions[[typ, typ]] *= factor;
+        }
+    }
+
+    /// Moves each base attraction a fraction `amount` of the way towards the average of the
+    /// attractions next to it in the matrix, where the source or the target is one type away.
+    /// Attractions along the edges of the matrix only average the neighbors inside of it. This
+    /// turns noisy attractions into smoother gradients between types.
+    pub fn smooth(&mut self, amount: f64) {
+        self.smooth_with(amount, false);
+    }
+
+    /// Like `smooth`, but the matrix wraps around at the edges, so that the first and last types
+    /// are next to each other like the hues of their colors.
+    pub fn smooth_wrapping(&mut self, amount: f64) {
+        self.smooth_with(amount, true);
+    }
+
+    fn smooth_with(&mut self, amount: f64, wrap: bool) {
+        let num_types = self.num_types() as isize;
+        let old = &self.base_attractions;
+        self.base_attractions = Matrix::from_fn(old.size, |index| {
+            let mut sum = 0.0;
+            let mut count = 0;
+            for offset in [[-1, 0], [1, 0], [0, -1], [0, 1]] {
+                let neighbor = [0, 1].map(|axis| {
+                    let typ = index[axis] as isize + offset[axis];
+                    if wrap {
+                        typ.rem_euclid(num_types)
+                    } else {
+                        typ
+                    }
+                });
+                if neighbor.iter().all(|typ| (0..num_types).contains(typ)) {
+                    sum += old[neighbor.map(|typ| typ as usize)];
+                    count += 1;
+                }
+            }
+            if count == 0 {
+                return old[index];
+            }
+            let mean = sum / count as f64;
+            old[index] + (mean - old[index]) * amount
+        });
+    }
+
+    /// Moves each base attraction a fraction `t` of the way towards the matching value in
+    /// `target`. Returns `None` without changing anything if `target` is a different size.
+    pub fn lerp_toward(&mut self, target: &Matrix<f64>, t: f64) -> Option<()> {
+        if target.size != self.base_attractions.size {
+            return None;
+        }
+        for (attraction, target) in self
+            .base_attractions
+            .data
+            .iter_mut()
+            .zip(target.data.iter())
+        {
+            *attraction += (target - *attraction) * t;
+        }
+        Some(())
+    }
+
+    /// Chooses new random base attractions within the `random_range`, keeping the colors and
+    /// scales.
+    pub fn randomize_attractions(&mut self) {
+        let mut rng = rand::thread_rng();
+        let range = self.random_range;
+        for attraction in self.base_attractions.data.iter_mut() {
+            *attraction = rng.gen_range(-range..=range);
+        }
+    }
+
+    /// The largest base attraction in either direction that `randomize_attractions` and `resize`
+    /// choose. This is 1 unless it has been changed.
+    pub fn random_range(&self) -> f64 {
+        self.random_range
+    }
+
+    /// Negative ranges are treated as 0.
+    pub fn set_random_range(&mut self, range: f64) {
+        self.random_range = range.max(0.0);
+    }
+
+    /// Moves every base attraction by a random amount of up to `amount` in either direction,
+    /// keeping them within the `random_range`, or between -1 and 1 if that is smaller, which is
+    /// the same limit the attraction sliders use.
+    pub fn jitter(&mut self, amount: f64) {
+        if amount <= 0.0 {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        let limit = self.random_range.max(1.0);
+        for attraction in self.base_attractions.data.iter_mut() {
+            *attraction = (*attraction + rng.gen_range(-amount..=amount)).clamp(-limit, limit);
+        }
+    }
+
+    /// Changes the number of types, keeping the attractions and colors between the types that
+    /// remain. New types get random attractions within the `random_range` and a color from the
+    /// color wheel.
+    pub fn resize(&mut self, num_types: usize) {
+        let mut rng = rand::thread_rng();
+        let range = self.random_range;
+        let old_num_types = self.num_types();
+        self.base_attractions = Matrix::from_fn([num_types; 2], |[source, target]| {
+            if source < old_num_types && target < old_num_types {
+                self.base_attractions[[source, target]]
+            } else {
+                rng.gen_range(-range..=range)
+            }
+        });
+        self.colors = (0..num_types)
+            .map(|typ| {
+                self.colors
+                    .get(typ)
+                    .copied()
+                    .unwrap_or_else(|| color::hsl_to_rgb(typ as f32 / num_types as f32, 1.0, 0.5))
+            })
+            .collect();
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn base_attractions(&self) -> &Matrix<f64> {
+        &self.base_attractions
+    }
+
+    /// Replaces the base attractions, keeping the colors and scales. Returns `None` without
+    /// changing anything if `base_attractions` is a different size.
+    pub fn set_base_attractions(&mut self, base_attractions: Matrix<f64>) -> Option<()> {
+        if base_attractions.size != self.base_attractions.size {
+            return None;
+        }
+        self.base_attractions = base_attractions;
+        Some(())
+    }
+
+    pub fn positive_scale(&self) -> f64 {
+        self.positive_scale
+    }
+
+    pub fn negative_scale(&self) -> f64 {
+        self.negative_scale
+    }
+
+    pub fn set_positive_scale(&mut self, positive_scale: f64) {
+        self.positive_scale = positive_scale;
+    }
+
+    pub fn set_negative_scale(&mut self, negative_scale: f64) {
+        self.negative_scale = negative_scale;
+    }
+
+    /// Sets both the positive and negative scale.
+    pub fn set_attraction_scale(&mut self, attraction_scale: f64) {
+        self.positive_scale = attraction_scale;
+        self.negative_scale = attraction_scale;
+    }
+
+    pub fn num_types(&self) -> usize {
+        self.base_attractions.size[0]
+    }
+}
+
+/// Linearly interpolates base attractions towards a target over a number of steps.
+#[derive(Clone, Debug)]
+pub struct AttractionMorph {
+    pub target: Matrix<f64>,
+    pub steps_left: usize,
+}
+
+impl AttractionMorph {
+    /// Takes one step towards the target. Returns `true` once the target has been reached, or if
+    /// it can't be reached because the matrices are different sizes.
+    pub fn step(&mut self, type_data: &mut ParticleTypeData) -> bool {
+        if self.steps_left == 0 {
+            return true;
+        }
+        // Moving 1/n of the remaining distance each step covers the same distance every step
+        let t = 1.0 / self.steps_left as f64;
+        self.steps_left -= 1;
+        type_data.lerp_toward(&self.target, t).is_none() || self.steps_left == 0
+    }
+}
+
+/// Converts a color with straight alpha, like the colors of `ParticleTypeData`, to premultiplied
+/// alpha, where the color channels are already multiplied by the alpha.
+///
+/// Particles are drawn with premultiplied alpha, so that colors stay correct when transparent
+/// particles are blended over each other or read back by effects like bloom.
+pub fn premultiplied(color: Color) -> Color {
+    Color::new(
+        color.r * color.a,
+        color.g * color.a,
+        color.b * color.a,
+        color.a,
+    )
+}
+
+thread_local! {
+    /// Draws shapes with premultiplied alpha blending. Materials can only be made once macroquad
+    /// has a window, so this is loaded the first time particles are drawn.
+    static PREMULTIPLIED_MATERIAL: OnceCell<Material> = const { OnceCell::new() };
+}
+
+fn load_premultiplied_material() -> Material {
+    material::load_material(
+        ShaderSource::Glsl {
+            vertex: PREMULTIPLIED_VERTEX_SHADER,
+            fragment: PREMULTIPLIED_FRAGMENT_SHADER,
+        },
+        MaterialParams {
+            pipeline_params: PipelineParams {
+                // The source is premultiplied, so it is added as it is in stead of being
+                // multiplied by its alpha like macroquad's default blending does
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::One,
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+// The same as macroquad's default shaders, which only differ from this material in how they blend
+const PREMULTIPLIED_VERTEX_SHADER: &str = "#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}
+";
+
+const PREMULTIPLIED_FRAGMENT_SHADER: &str = "#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = color * texture2D(Texture, uv);
+}
+";
+
+/// Draws a line made of dashes that are a fixed multiple of its thickness, so they look the same
+/// at any zoom.
+fn draw_dashed_line(start: Vec2, end: Vec2, thickness: f32, color: Color) {
+    let dash_length = thickness * 6.0;
+    let length = start.distance(end);
+    let direction = (end - start) / length.max(f32::EPSILON);
+    let mut distance = 0.0;
+    while distance < length {
+        let dash_start = start + direction * distance;
+        let dash_end = start + direction * (distance + dash_length).min(length);
+        shapes::draw_line(
+            dash_start.x,
+            dash_start.y,
+            dash_end.x,
+            dash_end.y,
+            thickness,
+            color,
+        );
+        distance += dash_length * 2.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_particles_steps_two_particles_together() {
+        let type_data = ParticleTypeData::new_from_fn(1, 5.0, |_| 1.0);
+        let particles = vec![
+            Particle::new([100.0, 100.0], [0.0, 0.0], 0),
+            Particle::new([150.0, 100.0], [0.0, 0.0], 0),
+        ];
+        let mut simulation = ParticleSimulation::with_particles(
+            100.0,
+            [4, 4],
+            ParticleSimulationParams::default(),
+            type_data,
+            particles,
+        );
+        assert_eq!(simulation.num_particles(), 2);
+
+        simulation.step_simulation();
+
+        // Each is pulled towards the other with a velocity of 5 / 50^2 * 50 = 0.1, and moves
+        // half of its velocity each step
+        let mut positions: Vec<_> = simulation.particles().map(|p| p.position).collect();
+        positions.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        assert!((positions[0][0] - 100.05).abs() < 1e-9);
+        assert!((positions[1][0] - 149.95).abs() < 1e-9);
+        assert_eq!(positions[0][1], 100.0);
+        assert_eq!(positions[1][1], 100.0);
+    }
+
+    #[test]
+    fn with_particles_drops_particles_outside_when_deleting() {
+        let params = ParticleSimulationParams {
+            edge_type: EdgeType::Deleting,
+            ..Default::default()
+        };
+        let particles = vec![
+            Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+            Particle::new([-10.0, 50.0], [0.0, 0.0], 0),
+        ];
+        let simulation = ParticleSimulation::with_particles(
+            100.0,
+            [2, 2],
+            params,
+            ParticleTypeData::new_random(1, 1.0),
+            particles,
+        );
+        assert_eq!(simulation.num_particles(), 1);
+    }
+
+    #[test]
+    fn circle_spawning_stays_within_radius() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([10, 10])
+            .random_type_data(3, 1.0)
+            .seed(1)
+            .build();
+        let center = [500.0, 500.0];
+        simulation.add_particles_in_circle(center, 120.0, 1000);
+
+        assert_eq!(simulation.num_particles(), 1000);
+        for particle in simulation.particles() {
+            let distance =
+                (particle.position[0] - center[0]).hypot(particle.position[1] - center[1]);
+            assert!(distance <= 120.0 + 1e-9, "{distance} is outside the circle");
+        }
+    }
+
+    #[test]
+    fn ring_spawning_stays_between_radii() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([10, 10])
+            .random_type_data(3, 1.0)
+            .seed(2)
+            .build();
+        let center = [500.0, 500.0];
+        simulation.add_particles_in_ring(center, 80.0, 120.0, 1000);
+
+        assert_eq!(simulation.num_particles(), 1000);
+        for particle in simulation.particles() {
+            let distance =
+                (particle.position[0] - center[0]).hypot(particle.position[1] - center[1]);
+            assert!((80.0 - 1e-9..=120.0 + 1e-9).contains(&distance));
+        }
+    }
+
+    #[test]
+    fn grid_spawning_fills_the_grid() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([3, 2])
+            .random_type_data(3, 1.0)
+            .build();
+        simulation.add_particles_in_grid(50.0);
+        assert_eq!(simulation.num_particles(), 6 * 4);
+    }
+
+    #[test]
+    fn clustered_particles_gather_by_type() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([20, 20])
+            .random_type_data(4, 1.0)
+            .seed(3)
+            .build();
+        let spread = 20.0;
+        simulation.add_clustered_particles(200, spread);
+
+        let mut centers = Vec::new();
+        for typ in 0..4 {
+            let positions: Vec<_> = simulation
+                .particles()
+                .filter(|particle| particle.typ == typ)
+                .map(|particle| particle.position)
+                .collect();
+            let count = positions.len() as f64;
+            let center = [0, 1].map(|axis| positions.iter().map(|p| p[axis]).sum::<f64>() / count);
+            let mean_distance = positions
+                .iter()
+                .map(|p| (p[0] - center[0]).hypot(p[1] - center[1]))
+                .sum::<f64>()
+                / count;
+            assert!(
+                mean_distance < 2.0 * spread,
+                "type {typ} is spread {mean_distance}"
+            );
+            centers.push(center);
+        }
+        for (i, a) in centers.iter().enumerate() {
+            for b in &centers[i + 1..] {
+                assert!((a[0] - b[0]).hypot(a[1] - b[1]) > 4.0 * spread);
+            }
+        }
+    }
+
+    #[test]
+    fn particles_wrap_on_a_narrow_grid() {
+        let params = ParticleSimulationParams {
+            edge_type: EdgeType::Wrapping,
+            ..Default::default()
+        };
+        let particles = vec![
+            // Crosses the end of the long axis
+            Particle::new([50.0, 4990.0], [0.0, 40.0], 0),
+            // Crosses the edge of the narrow axis
+            Particle::new([95.0, 2550.0], [20.0, 0.0], 0),
+        ];
+        let mut simulation = ParticleSimulation::with_particles(
+            100.0,
+            [1, 50],
+            params,
+            ParticleTypeData::new_from_fn(1, 0.0, |_| 0.0),
+            particles,
+        );
+        assert_eq!(simulation.size(), [100.0, 5000.0]);
+
+        simulation.step_simulation();
+
+        assert_eq!(simulation.num_particles(), 2);
+        let first = &simulation.buckets[[0, 0]];
+        assert_eq!(first.len(), 1);
+        assert!((first[0].position[0] - 50.0).abs() < 1e-9);
+        assert!((first[0].position[1] - 10.0).abs() < 1e-9);
+        let second = &simulation.buckets[[0, 25]];
+        assert_eq!(second.len(), 1);
+        assert!((second[0].position[0] - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn set_attraction_is_scaled() {
+        let mut type_data = ParticleTypeData::new_from_fn(3, 4.0, |_| 0.0);
+        type_data.set_negative_scale(2.0);
+
+        assert_eq!(type_data.set_attraction(0, 2, 0.5), Some(()));
+        assert_eq!(type_data.get_base_attraction(0, 2), 0.5);
+        assert_eq!(type_data.get_attraction(0, 2), 2.0);
+        assert_eq!(type_data.set_attraction(2, 1, -0.5), Some(()));
+        assert_eq!(type_data.get_attraction(2, 1), -1.0);
+        // Only the one attraction changes
+        assert_eq!(type_data.get_attraction(2, 0), 0.0);
+
+        assert_eq!(type_data.set_attraction(3, 0, 1.0), None);
+        assert_eq!(type_data.set_attraction(0, 3, 1.0), None);
+    }
+
+    #[test]
+    fn inverting_twice_restores_attractions() {
+        let mut type_data = ParticleTypeData::new_random(5, 1.0);
+        let original = type_data.base_attractions().clone();
+
+        type_data.invert();
+        for (inverted, original) in type_data
+            .base_attractions()
+            .data
+            .iter()
+            .zip(&*original.data)
+        {
+            assert_eq!(*inverted, -original);
+        }
+        type_data.invert();
+        assert_eq!(type_data.base_attractions().data, original.data);
+    }
+
+    #[test]
+    fn scale_diagonal_leaves_other_attractions() {
+        let mut type_data = ParticleTypeData::new_from_fn(4, 1.0, |[source, target]| {
+            (source * 4 + target) as f64 / 16.0
+        });
+        let original = type_data.base_attractions().clone();
+
+        type_data.scale_diagonal(3.0);
+        for source in 0..4 {
+            for target in 0..4 {
+                let expected = if source == target {
+                    original[[source, target]] * 3.0
+                } else {
+                    original[[source, target]]
+                };
+                assert_eq!(type_data.get_base_attraction(source, target), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn lerp_toward_halfway() {
+        let mut type_data =
+            ParticleTypeData::new_from_fn(
+                2,
+                1.0,
+                |[source, target]| {
+                    if source == target {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                },
+            );
+        let target = Matrix::from_fn([2, 2], |[source, _]| source as f64 * 0.5);
+
+        assert_eq!(type_data.lerp_toward(&target, 0.5), Some(()));
+        assert_eq!(type_data.get_base_attraction(0, 0), 0.5);
+        assert_eq!(type_data.get_base_attraction(0, 1), -0.5);
+        assert_eq!(type_data.get_base_attraction(1, 0), -0.25);
+        assert_eq!(type_data.get_base_attraction(1, 1), 0.75);
+
+        let wrong_size = Matrix::from_element([3, 3], 0.0);
+        assert_eq!(type_data.lerp_toward(&wrong_size, 0.5), None);
+        assert_eq!(type_data.get_base_attraction(0, 0), 0.5);
+    }
+
+    #[test]
+    fn same_seed_steps_the_same() {
+        let type_data = ParticleTypeData::new_random(6, 5.0);
+        let mut simulations = [0, 1].map(|_| {
+            ParticleSimulation::builder()
+                .buckets([8, 6])
+                .type_data(type_data.clone())
+                .seed(42)
+                .random_particles(800)
+                .build()
+        });
+        assert_eq!(simulations[0].state_hash(), simulations[1].state_hash());
+
+        for simulation in &mut simulations {
+            simulation.step_n(50);
+        }
+        assert_eq!(simulations[0].state_hash(), simulations[1].state_hash());
+    }
+
+    #[test]
+    fn coincident_particles_separate_reproducibly() {
+        let run = || {
+            let particles = vec![
+                Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+                Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+            ];
+            let mut simulation = ParticleSimulation::builder()
+                .buckets([1, 1])
+                .type_data(ParticleTypeData::new_from_fn(1, 1.0, |_| 0.0))
+                .particles(particles)
+                .seed(7)
+                .build();
+            simulation.step_simulation();
+            let mut positions: Vec<_> = simulation.particles().map(|p| p.position).collect();
+            positions.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+            positions
+        };
+
+        let positions = run();
+        assert_ne!(positions[0], positions[1]);
+        // Pushed apart in opposite directions
+        for (first, second) in positions[0].iter().zip(positions[1]) {
+            assert!((first - 50.0 + second - 50.0).abs() < 1e-9);
+        }
+        for _ in 0..5 {
+            assert_eq!(run(), positions);
+        }
+    }
+
+    #[test]
+    fn delta_wrapped_crosses_edges_only_when_wrapping() {
+        let wrapping = ParticleSimulationParams {
+            edge_type: EdgeType::Wrapping,
+            ..Default::default()
+        };
+        let simulation = ParticleSimulation::builder()
+            .buckets([4, 2])
+            .params(wrapping)
+            .build();
+        let a = [5.0, 190.0];
+        let b = [395.0, 10.0];
+        let delta = simulation.delta_wrapped(a, b);
+        assert!((delta[0] + 10.0).abs() < 1e-9);
+        assert!((delta[1] - 20.0).abs() < 1e-9);
+
+        let bouncing = ParticleSimulation::builder().buckets([4, 2]).build();
+        assert_eq!(bouncing.delta_wrapped(a, b), [390.0, -180.0]);
+    }
+
+    #[test]
+    fn subdividing_keeps_the_size_and_range() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([6, 4])
+            .random_type_data(3, 1.0)
+            .random_particles(500)
+            .build();
+
+        simulation.set_subdivision(2.0);
+        assert_eq!(simulation.grid_size(), [12, 8]);
+        assert_eq!(simulation.size(), [600.0, 400.0]);
+        assert_eq!(simulation.bucket_size(), 50.0);
+        assert_eq!(simulation.interaction_range(), 100.0);
+        assert_eq!(simulation.num_particles(), 500);
+
+        // 600 / (100 / 1.5) is 9 buckets, and 400 / (100 / 1.5) is rounded up to 6
+        simulation.set_subdivision(1.5);
+        assert_eq!(simulation.grid_size(), [9, 6]);
+        assert_eq!(simulation.num_particles(), 500);
+
+        simulation.set_subdivision(0.5);
+        assert_eq!(simulation.subdivision(), 1.0);
+        assert_eq!(simulation.bucket_size(), 100.0);
+    }
+
+    #[test]
+    fn subdivided_grid_finds_the_same_interactions() {
+        let type_data = ParticleTypeData::new_random(5, 5.0);
+        let mut simulations = [1.0, 2.0, 2.5].map(|factor| {
+            let mut simulation = ParticleSimulation::builder()
+                .buckets([6, 6])
+                .type_data(type_data.clone())
+                .seed(5)
+                .random_particles(600)
+                .build();
+            simulation.set_subdivision(factor);
+            simulation.set_count_interactions(true);
+            simulation
+        });
+        for simulation in &mut simulations {
+            simulation.step_simulation();
+        }
+
+        let positions = |simulation: &ParticleSimulation| {
+            let mut positions: Vec<_> = simulation
+                .particles()
+                .map(|p| (p.id(), p.position))
+                .collect();
+            positions.sort_by_key(|&(id, _)| id);
+            positions
+        };
+        let reference = positions(&simulations[0]);
+        for simulation in &simulations[1..] {
+            assert_eq!(
+                simulation.interaction_count(),
+                simulations[0].interaction_count()
+            );
+            for (a, b) in reference.iter().zip(positions(simulation)) {
+                assert_eq!(a.0, b.0);
+                assert!((a.1[0] - b.1[0]).abs() < 1e-9 && (a.1[1] - b.1[1]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn auto_subdivision_limits_occupancy() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([4, 4])
+            .random_type_data(3, 1.0)
+            .random_particles(1600)
+            .build();
+        // 100 particles per bucket need a subdivision of 3 to get down to 20
+        simulation.set_auto_subdivision(Some(20.0));
+        simulation.step_simulation();
+        assert_eq!(simulation.subdivision(), 3.0);
+        assert_eq!(simulation.grid_size(), [12, 12]);
+
+        simulation.clear_particles();
+        simulation.step_simulation();
+        assert_eq!(simulation.subdivision(), 1.0);
+        assert_eq!(simulation.grid_size(), [4, 4]);
+    }
+
+    #[test]
+    fn gradient_falls_with_distance_between_types() {
+        let num_types = 8;
+        let type_data = ParticleTypeData::new_gradient(num_types, 1.0, 2.0);
+        for source in 0..num_types {
+            assert_eq!(type_data.get_base_attraction(source, source), 1.0);
+            // Going around the color wheel, attractions fall until the furthest type and then
+            // rise again
+            let row: Vec<_> = (0..=num_types)
+                .map(|offset| type_data.get_base_attraction(source, (source + offset) % num_types))
+                .collect();
+            for offset in 0..num_types / 2 {
+                assert!(row[offset + 1] < row[offset]);
+            }
+            for offset in num_types / 2..num_types {
+                assert!(row[offset + 1] > row[offset]);
+            }
+            assert!(row.iter().all(|x| (-1.0..=1.0).contains(x)));
+        }
+    }
+
+    #[test]
+    fn antisymmetrize_negates_across_the_diagonal() {
+        let mut type_data = ParticleTypeData::new_random(6, 1.0);
+        let original = type_data.base_attractions().clone();
+        type_data.antisymmetrize();
+
+        for source in 0..6 {
+            for target in 0..6 {
+                if source == target {
+                    continue;
+                }
+                let attraction = type_data.get_base_attraction(source, target);
+                assert_eq!(attraction, -type_data.get_base_attraction(target, source));
+                let expected = (original[[source, target]] - original[[target, source]]) / 2.0;
+                assert!((attraction - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn poisson_spawning_keeps_particles_apart() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([5, 5])
+            .random_type_data(3, 1.0)
+            .seed(11)
+            .build();
+        let min_distance = 20.0;
+        let added = simulation.add_particles_poisson(150, min_distance);
+        assert_eq!(added, 150);
+        assert_eq!(simulation.num_particles(), 150);
+
+        let positions: Vec<_> = simulation.particles().map(|p| p.position).collect();
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                assert!((a[0] - b[0]).hypot(a[1] - b[1]) >= min_distance);
+            }
+        }
+
+        // There isn't room for this many, so it gives up early in stead
+        let added = simulation.add_particles_poisson(10_000, min_distance);
+        assert!(added < 10_000);
+        assert_eq!(simulation.num_particles(), 150 + added);
+    }
+
+    #[test]
+    fn new_with_colors_uses_the_given_colors() {
+        let colors: Box<[Color]> = Box::new([
+            Color::new(0.1, 0.2, 0.3, 1.0),
+            Color::new(0.9, 0.8, 0.7, 0.5),
+        ]);
+        let type_data = ParticleTypeData::new_with_colors(2, 1.0, |_| 0.0, colors.clone()).unwrap();
+        assert_eq!(type_data.colors(), &*colors);
+
+        assert!(ParticleTypeData::new_with_colors(3, 1.0, |_| 0.0, colors).is_none());
+    }
+
+    #[test]
+    fn soft_boundary_pushes_inwards() {
+        let boundary = SoftBoundary {
+            margin: 50.0,
+            strength: 0.5,
+        };
+        let size = [400.0, 300.0];
+        assert_eq!(boundary.impulse([200.0, 150.0], size), [0.0, 0.0]);
+        assert_eq!(boundary.impulse([25.0, 150.0], size), [0.25, 0.0]);
+        assert_eq!(boundary.impulse([200.0, 300.0], size), [0.0, -0.5]);
+
+        // In a simulation, a particle in the margin gains velocity away from the edge
+        let params = ParticleSimulationParams {
+            soft_boundary: Some(boundary),
+            ..Default::default()
+        };
+        let mut simulation = ParticleSimulation::with_particles(
+            100.0,
+            [4, 3],
+            params,
+            ParticleTypeData::new_from_fn(1, 0.0, |_| 0.0),
+            vec![Particle::new([390.0, 10.0], [0.0, 0.0], 0)],
+        );
+        simulation.step_simulation();
+        let velocity = simulation.particles().next().unwrap().velocity;
+        assert!(velocity[0] < 0.0);
+        assert!(velocity[1] > 0.0);
+    }
+
+    #[test]
+    fn repulsion_distance_sets_the_spacing() {
+        let spacing = |repulsion_distance: f64| {
+            let params = ParticleSimulationParams {
+                repulsion_distance,
+                ..Default::default()
+            };
+            let mut simulation = ParticleSimulation::with_particles(
+                100.0,
+                [2, 1],
+                params,
+                ParticleTypeData::new_from_fn(1, 1.0, |_| 1.0),
+                vec![
+                    Particle::new([70.0, 50.0], [0.0, 0.0], 0),
+                    Particle::new([130.0, 50.0], [0.0, 0.0], 0),
+                ],
+            );
+            simulation.step_n(500);
+            let positions: Vec<_> = simulation.particles().map(|p| p.position).collect();
+            (positions[0][0] - positions[1][0]).hypot(positions[0][1] - positions[1][1])
+        };
+
+        let close = spacing(10.0);
+        let far = spacing(20.0);
+        // The pair settles just outside of the repulsion distance
+        assert!((10.0..12.0).contains(&close), "{close}");
+        assert!((20.0..22.0).contains(&far), "{far}");
+    }
+
+    #[test]
+    fn particles_overlap_without_close_range_repulsion() {
+        // Without softening the unbounded force at close range flings the pair apart in stead of
+        // letting them settle on top of each other
+        let spacing = |close_range_repulsion: bool| {
+            let params = ParticleSimulationParams {
+                close_range_repulsion,
+                softening: 25.0,
+                ..Default::default()
+            };
+            let mut simulation = ParticleSimulation::with_particles(
+                100.0,
+                [2, 1],
+                params,
+                ParticleTypeData::new_from_fn(1, 1.0, |_| 1.0),
+                vec![
+                    Particle::new([70.0, 50.0], [0.0, 0.0], 0),
+                    Particle::new([130.0, 50.0], [0.0, 0.0], 0),
+                ],
+            );
+            simulation.step_n(500);
+            let positions: Vec<_> = simulation.particles().map(|p| p.position).collect();
+            (positions[0][0] - positions[1][0]).hypot(positions[0][1] - positions[1][1])
+        };
+
+        let repelled = spacing(true);
+        let overlapping = spacing(false);
+        assert!(repelled > 10.0, "{repelled}");
+        assert!(overlapping < 1.0, "{overlapping}");
+    }
+
+    #[test]
+    fn deleting_edge_removes_one_particle() {
+        let params = ParticleSimulationParams {
+            edge_type: EdgeType::Deleting,
+            ..Default::default()
+        };
+        let mut simulation = ParticleSimulation::with_particles(
+            100.0,
+            [3, 3],
+            params,
+            ParticleTypeData::new_from_fn(1, 0.0, |_| 0.0),
+            vec![
+                Particle::new([295.0, 150.0], [20.0, 0.0], 0),
+                Particle::new([150.0, 150.0], [0.0, 0.0], 0),
+                Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+            ],
+        );
+        simulation.step_simulation();
+        assert_eq!(simulation.num_particles(), 2);
+        assert!(simulation.particles().all(|p| p.position[0] < 200.0));
+    }
+
+    #[test]
+    fn coincident_particles_strategies() {
+        let step = |coincident_particles| {
+            let params = ParticleSimulationParams {
+                coincident_particles,
+                ..Default::default()
+            };
+            let mut simulation = ParticleSimulation::with_particles(
+                100.0,
+                [1, 1],
+                params,
+                ParticleTypeData::new_from_fn(1, 1.0, |_| 1.0),
+                vec![
+                    Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+                    Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+                ],
+            );
+            simulation.step_simulation();
+            simulation
+        };
+
+        let skipped = step(CoincidentParticles::Skip);
+        assert_eq!(skipped.num_particles(), 2);
+        for particle in skipped.particles() {
+            assert_eq!(particle.position, [50.0, 50.0]);
+            assert_eq!(particle.velocity, [0.0, 0.0]);
+        }
+
+        let merged = step(CoincidentParticles::Merge);
+        assert_eq!(merged.num_particles(), 1);
+
+        let nudged = step(CoincidentParticles::Nudge);
+        assert!(nudged.particles().all(|p| p.position != [50.0, 50.0]));
+    }
+
+    #[test]
+    fn type_caps_only_limit_their_type() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([4, 4])
+            .random_type_data(3, 1.0)
+            .seed(4)
+            .build();
+        simulation.set_type_cap(1, Some(10));
+
+        simulation.add_typed_particles_in_circle([200.0, 200.0], 100.0, 30, 1);
+        simulation.add_typed_particles_in_circle([200.0, 200.0], 100.0, 30, 2);
+        assert_eq!(simulation.type_counts(), [0, 10, 30]);
+
+        simulation.add_random_particles(300);
+        let counts = simulation.type_counts();
+        assert_eq!(counts[1], 10);
+        assert!(counts[0] > 0 && counts[2] > 30);
+
+        // Particles that are inserted directly aren't limited
+        simulation.insert_particle(Particle::new([10.0, 10.0], [0.0, 0.0], 1));
+        assert_eq!(simulation.type_counts()[1], 11);
+    }
+
+    #[test]
+    fn jitter_of_zero_changes_nothing() {
+        let mut type_data = ParticleTypeData::new_random(5, 1.0);
+        let original = type_data.base_attractions().clone();
+        type_data.jitter(0.0);
+        assert_eq!(type_data.base_attractions().data, original.data);
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        let mut type_data = ParticleTypeData::new_random(6, 1.0);
+        for _ in 0..20 {
+            type_data.jitter(0.5);
+        }
+        assert_eq!(type_data.base_attractions().size, [6, 6]);
+        assert!(type_data
+            .base_attractions()
+            .data
+            .iter()
+            .all(|attraction| (-1.0..=1.0).contains(attraction)));
+
+        // A wider random range isn't crushed down to 1
+        let mut type_data = ParticleTypeData::new_from_fn(3, 1.0, |_| 3.5);
+        type_data.set_random_range(4.0);
+        type_data.jitter(0.25);
+        assert_eq!(type_data.base_attractions().size, [3, 3]);
+        assert!(type_data
+            .base_attractions()
+            .data
+            .iter()
+            .all(|attraction| (3.25..=3.75).contains(attraction)));
+        for _ in 0..20 {
+            type_data.jitter(1.0);
+        }
+        assert!(type_data
+            .base_attractions()
+            .data
+            .iter()
+            .all(|attraction| (-4.0..=4.0).contains(attraction)));
+    }
+
+    #[test]
+    fn random_attractions_stay_in_a_custom_range() {
+        let type_data = ParticleTypeData::new_random_in_range(8, 1.0, 3.0);
+        assert_eq!(type_data.random_range(), 3.0);
+        let attractions = &type_data.base_attractions().data;
+        assert_eq!(attractions.len(), 64);
+        assert!(attractions
+            .iter()
+            .all(|attraction| (-3.0..=3.0).contains(attraction)));
+        assert!(attractions.iter().any(|attraction| attraction.abs() > 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "bucket size must be positive and finite")]
+    fn zero_bucket_size_is_rejected() {
+        ParticleSimulation::with_particles(
+            0.0,
+            [2, 2],
+            ParticleSimulationParams::default(),
+            ParticleTypeData::new_random(2, 1.0),
+            Vec::new(),
+        );
+    }
+
+    #[test]
+    fn premultiplying_a_half_transparent_color() {
+        let straight = Color::new(1.0, 0.5, 0.2, 0.5);
+        let color = premultiplied(straight);
+        assert_eq!([color.r, color.g, color.b, color.a], [0.5, 0.25, 0.1, 0.5]);
+
+        // Blending it the premultiplied way gives the same result as blending the straight color
+        // the straight way
+        let straight_channels = [straight.r, straight.g, straight.b];
+        let premultiplied_channels = [color.r, color.g, color.b];
+        let background = [0.2, 0.4, 0.6];
+        for ((straight_channel, channel), destination) in straight_channels
+            .into_iter()
+            .zip(premultiplied_channels)
+            .zip(background)
+        {
+            let straight_blend = straight_channel * straight.a + destination * (1.0 - straight.a);
+            let premultiplied_blend = channel + destination * (1.0 - color.a);
+            assert!((premultiplied_blend - straight_blend).abs() < 1e-6);
+        }
+
+        let opaque = Color::new(0.3, 0.6, 0.9, 1.0);
+        assert_eq!(premultiplied(opaque), opaque);
+    }
+
+    #[test]
+    fn retyping_can_reset_velocity() {
+        let retype = |reset_velocity: bool| {
+            let particles = (0..4)
+                .map(|i| Particle::new([50.0 + i as f64 * 10.0, 50.0], [2.0, -1.0], 0))
+                .collect();
+            let mut simulation = ParticleSimulation::with_particles(
+                100.0,
+                [2, 2],
+                ParticleSimulationParams::default(),
+                ParticleTypeData::new_random(2, 1.0),
+                particles,
+            );
+            simulation.set_reset_velocity_on_retype(reset_velocity);
+            // Retype the even particles, and move the odd ones without changing their type
+            simulation.edit_particles(|particle| {
+                if particle.id() % 2 == 0 {
+                    particle.typ = 1;
+                } else {
+                    particle.position[1] += 5.0;
+                }
+                true
+            });
+            let mut particles: Vec<_> = simulation
+                .particles()
+                .map(|particle| (particle.id(), particle.typ, particle.velocity))
+                .collect();
+            particles.sort_by_key(|&(id, _, _)| id);
+            particles
+        };
+
+        for (id, typ, velocity) in retype(false) {
+            assert_eq!(typ, if id % 2 == 0 { 1 } else { 0 });
+            assert_eq!(velocity, [2.0, -1.0]);
+        }
+        for (id, _, velocity) in retype(true) {
+            let expected = if id % 2 == 0 { [0.0, 0.0] } else { [2.0, -1.0] };
+            assert_eq!(velocity, expected);
+        }
+    }
+
+    #[test]
+    fn removed_types_are_clamped_or_randomized() {
+        let particles = (0..6)
+            .map(|typ| Particle::new([50.0 + typ as f64 * 10.0, 50.0], [1.0, 0.0], typ))
+            .collect();
+        let mut simulation = ParticleSimulation::with_particles(
+            100.0,
+            [2, 2],
+            ParticleSimulationParams::default(),
+            ParticleTypeData::new_random(6, 1.0),
+            particles,
+        );
+        let mut randomized = simulation.clone();
+
+        simulation.set_reset_velocity_on_retype(true);
+        simulation.set_num_types(3, RemovedTypes::Clamp);
+        let mut types: Vec<_> = simulation.particles().map(|p| (p.id(), p.typ)).collect();
+        types.sort();
+        assert_eq!(
+            types.iter().map(|&(_, typ)| typ).collect::<Vec<_>>(),
+            [0, 1, 2, 2, 2, 2]
+        );
+        for particle in simulation.particles() {
+            let expected = if particle.id() < 3 {
+                [1.0, 0.0]
+            } else {
+                [0.0, 0.0]
+            };
+            assert_eq!(particle.velocity, expected);
+        }
+
+        randomized.set_num_types(3, RemovedTypes::Randomize);
+        assert_eq!(randomized.num_particles(), 6);
+        assert!(randomized.particles().all(|p| p.typ < 3));
+    }
+
+    #[test]
+    fn step_stats_match_a_serial_count() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([5, 4])
+            .random_type_data(4, 5.0)
+            .seed(9)
+            .random_particles(400)
+            .build();
+        simulation.set_count_interactions(true);
+        assert_eq!(simulation.step_stats(), Some(StepStats::default()));
+
+        let positions: Vec<_> = simulation.particles().map(|p| p.position).collect();
+        let range = simulation.interaction_range();
+        let mut interactions = 0;
+        for (i, a) in positions.iter().enumerate() {
+            for (j, b) in positions.iter().enumerate() {
+                let distance_squared = (b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2);
+                if i != j && distance_squared <= range.powi(2) {
+                    interactions += 1;
+                }
+            }
+        }
+
+        simulation.move_particles();
+        let stats = simulation.step_stats().unwrap();
+        assert_eq!(stats.interactions, interactions);
+        assert_eq!(simulation.interaction_count(), Some(interactions));
+        let kinetic_energy: f64 = simulation
+            .particles()
+            .map(|p| (p.velocity[0].powi(2) + p.velocity[1].powi(2)) / 2.0)
+            .sum();
+        assert!((stats.kinetic_energy - kinetic_energy).abs() < 1e-9 * kinetic_energy.max(1.0));
+
+        simulation.set_count_interactions(false);
+        assert_eq!(simulation.step_stats(), None);
+    }
+
+    #[test]
+    fn softening_bounds_close_range_forces() {
+        let impulse = |softening: f64| {
+            let params = ParticleSimulationParams {
+                prevent_particle_ejecting: false,
+                softening,
+                ..Default::default()
+            };
+            let type_data = ParticleTypeData::new_from_fn(1, 1.0, |_| 1.0);
+            let mut particle = Particle::new([50.0, 50.0], [0.0, 0.0], 0);
+            let other = Particle::new([50.0 + 1e-6, 50.0], [0.0, 0.0], 0);
+            assert!(particle
+                .update_with_particle(other, &type_data, &params, 100.0, || { unreachable!() }));
+            particle.velocity[0].hypot(particle.velocity[1])
+        };
+
+        // Without softening, the repulsion grows with the inverse of the distance
+        assert!(impulse(0.0) > 1e5);
+        assert!(impulse(1.0) < 1e-3);
+    }
+
+    #[test]
+    fn attractions_use_the_scale_for_their_sign() {
+        let mut type_data =
+            ParticleTypeData::new_from_fn(
+                2,
+                1.0,
+                |[source, target]| {
+                    if source == target {
+                        0.5
+                    } else {
+                        -0.5
+                    }
+                },
+            );
+        type_data.set_positive_scale(4.0);
+        type_data.set_negative_scale(10.0);
+        assert_eq!(type_data.get_attraction(0, 0), 2.0);
+        assert_eq!(type_data.get_attraction(0, 1), -5.0);
+
+        type_data.set_attraction_scale(3.0);
+        assert_eq!(type_data.get_attraction(1, 1), 1.5);
+        assert_eq!(type_data.get_attraction(1, 0), -1.5);
+    }
+
+    #[test]
+    fn inserting_out_of_bounds_changes_nothing() {
+        let mut simulation = ParticleSimulation::with_particles(
+            100.0,
+            [2, 2],
+            ParticleSimulationParams::default(),
+            ParticleTypeData::new_random(1, 1.0),
+            vec![Particle::new([50.0, 50.0], [0.0, 0.0], 0)],
+        );
+        for position in [[-1.0, 50.0], [50.0, 200.0], [-1.0, -1.0]] {
+            let particle = Particle::new(position, [0.0, 0.0], 0);
+            assert_eq!(simulation.insert_particle(particle), None);
+            assert_eq!(simulation.num_particles(), 1);
+        }
+
+        // No ids were used up by the failed inserts
+        let particle = Particle::new([150.0, 150.0], [0.0, 0.0], 0);
+        assert_eq!(simulation.insert_particle(particle), Some(()));
+        assert_eq!(simulation.num_particles(), 2);
+        let mut ids: Vec<_> = simulation
+            .particles()
+            .map(|particle| particle.id())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, [0, 1]);
+    }
+
+    #[test]
+    fn spawned_particles_stay_under_the_spawn_speed() {
+        let type_data = ParticleTypeData::new_random(3, 1.0);
+        let spawn = |speed: f64| {
+            let mut simulation = ParticleSimulation::builder()
+                .buckets([4, 4])
+                .type_data(type_data.clone())
+                .seed(5)
+                .build();
+            simulation.set_spawn_speed(speed);
+            simulation.add_random_particles(200);
+            simulation
+                .particles()
+                .map(|particle| particle.velocity)
+                .collect::<Vec<_>>()
+        };
+
+        let velocities = spawn(5.0);
+        let speeds: Vec<f64> = velocities.iter().map(|v| v[0].hypot(v[1])).collect();
+        assert!(speeds.iter().all(|&speed| speed <= 5.0 + 1e-9));
+        assert!(speeds.iter().any(|&speed| speed > 2.5));
+        assert_eq!(spawn(5.0), velocities);
+        assert!(spawn(0.0).iter().all(|&velocity| velocity == [0.0, 0.0]));
+    }
+
+    #[test]
+    fn spawning_stops_at_max_particles() {
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([4, 4])
+            .random_type_data(2, 1.0)
+            .build();
+        simulation.set_max_particles(150);
+        simulation.add_random_particles(100);
+        assert_eq!(simulation.num_particles(), 100);
+        assert!(!simulation.hit_particle_limit());
+
+        simulation.add_random_particles(100);
+        assert_eq!(simulation.num_particles(), 150);
+        assert!(simulation.hit_particle_limit());
+
+        let particle = Particle::new([50.0, 50.0], [0.0, 0.0], 0);
+        assert_eq!(simulation.insert_particle(particle), None);
+        simulation.add_particles_in_circle([200.0, 200.0], 100.0, 10);
+        assert_eq!(simulation.num_particles(), 150);
+
+        // Raising the limit lets spawning continue, and clears the flag
+        simulation.set_max_particles(160);
+        simulation.add_random_particles(5);
+        assert_eq!(simulation.num_particles(), 155);
+        assert!(!simulation.hit_particle_limit());
+    }
+
+    #[test]
+    fn prevent_particle_ejecting_flips_the_closest_repulsion() {
+        let impulse = |prevent_particle_ejecting: bool, distance: f64| {
+            let params = ParticleSimulationParams {
+                prevent_particle_ejecting,
+                ..Default::default()
+            };
+            let type_data = ParticleTypeData::new_from_fn(1, 1.0, |_| 1.0);
+            let mut particle = Particle::new([50.0, 50.0], [0.0, 0.0], 0);
+            let other = Particle::new([50.0 + distance, 50.0], [0.0, 0.0], 0);
+            assert!(particle
+                .update_with_particle(other, &type_data, &params, 100.0, || { unreachable!() }));
+            particle.velocity[0]
+        };
+
+        for distance in [0.1, 0.5, 0.9] {
+            assert!(impulse(true, distance) > 0.0);
+            assert!(impulse(false, distance) < 0.0);
+        }
+        // Further away both push the particles apart
+        assert!(impulse(true, 5.0) < 0.0);
+        assert_eq!(impulse(true, 5.0), impulse(false, 5.0));
+    }
+
+    #[test]
+    fn ejection_distance_sets_where_the_push_in_starts() {
+        let impulse = |ejection_distance: f64, distance: f64| {
+            let params = ParticleSimulationParams {
+                ejection_distance,
+                ..Default::default()
+            };
+            let type_data = ParticleTypeData::new_from_fn(1, 1.0, |_| 1.0);
+            let mut particle = Particle::new([50.0, 50.0], [0.0, 0.0], 0);
+            let other = Particle::new([50.0 + distance, 50.0], [0.0, 0.0], 0);
+            assert!(particle
+                .update_with_particle(other, &type_data, &params, 100.0, || { unreachable!() }));
+            particle.velocity[0]
+        };
+
+        assert!(impulse(1.0, 0.5) > 0.0);
+        assert!(impulse(1.0, 2.0) < 0.0);
+        assert!(impulse(3.0, 2.0) > 0.0);
+        assert!(impulse(3.0, 3.5) < 0.0);
+    }
+
+    #[test]
+    fn overlap_strength_only_scales_the_push_in() {
+        let impulse = |overlap_strength: f64, distance: f64| {
+            let params = ParticleSimulationParams {
+                overlap_strength,
+                ..Default::default()
+            };
+            let type_data = ParticleTypeData::new_from_fn(1, 1.0, |_| 1.0);
+            let mut particle = Particle::new([50.0, 50.0], [0.0, 0.0], 0);
+            let other = Particle::new([50.0 + distance, 50.0], [0.0, 0.0], 0);
+            assert!(particle
+                .update_with_particle(other, &type_data, &params, 100.0, || { unreachable!() }));
+            particle.velocity[0]
+        };
+
+        // With the push-in force being constant, the impulse is the strength itself
+        assert!((impulse(PARTICLE_RADIUS, 0.5) - PARTICLE_RADIUS).abs() < 1e-9);
+        assert!((impulse(20.0, 0.5) - 20.0).abs() < 1e-9);
+        assert!((impulse(20.0, 0.1) - 20.0).abs() < 1e-9);
+        // Further apart, neither the repulsion nor the attraction change
+        assert_eq!(impulse(20.0, 5.0), impulse(PARTICLE_RADIUS, 5.0));
+        assert_eq!(impulse(20.0, 50.0), impulse(PARTICLE_RADIUS, 50.0));
+    }
+
+    #[test]
+    fn brute_force_matches_the_grid_search() {
+        let type_data = ParticleTypeData::new_random(4, 5.0);
+        let step = |neighbor_search: NeighborSearch, subdivision: f64| {
+            let mut simulation = ParticleSimulation::builder()
+                .buckets([5, 4])
+                .params(ParticleSimulationParams {
+                    neighbor_search,
+                    ..Default::default()
+                })
+                .type_data(type_data.clone())
+                .seed(13)
+                .random_particles(400)
+                .build();
+            simulation.set_subdivision(subdivision);
+            simulation.step_n(20);
+            let mut particles: Vec<_> = simulation
+                .particles()
+                .map(|particle| (particle.id(), particle.position))
+                .collect();
+            particles.sort_by_key(|&(id, _)| id);
+            particles
+        };
+
+        for subdivision in [1.0, 2.0] {
+            let grid = step(NeighborSearch::Neighbors, subdivision);
+            assert_eq!(grid.len(), 400);
+            assert_eq!(step(NeighborSearch::BruteForce, subdivision), grid);
+            assert_eq!(step(NeighborSearch::Radius, subdivision), grid);
+        }
+    }
+
+    #[test]
+    fn step_count_counts_steps() {
+        let build = || {
+            ParticleSimulation::builder()
+                .buckets([3, 3])
+                .random_type_data(2, 1.0)
+                .random_particles(50)
+                .build()
+        };
+        let mut simulation = build();
+        assert_eq!(simulation.step_count(), 0);
+        simulation.step_n(7);
+        assert_eq!(simulation.step_count(), 7);
+        simulation.step_simulation();
+        simulation.move_particles();
+        simulation.organize_particles();
+        assert_eq!(simulation.step_count(), 8);
+
+        let mut copy = simulation.clone();
+        assert_eq!(copy.step_count(), 8);
+        copy.step_n(2);
+        assert_eq!(copy.step_count(), 10);
+        assert_eq!(simulation.step_count(), 8);
+
+        simulation.resize_grid([4, 4]);
+        assert_eq!(simulation.step_count(), 8);
+        assert_eq!(build().step_count(), 0);
+    }
+
+    #[test]
+    fn validate_accepts_a_valid_state() {
+        let particles = vec![Particle::new([50.0, 50.0], [1.0, 0.0], 1)];
+        let simulation = ParticleSimulation::try_with_particles(
+            100.0,
+            [2, 2],
+            ParticleSimulationParams::default(),
+            ParticleTypeData::new_random(2, 1.0),
+            particles,
+            Some(1),
+        )
+        .unwrap();
+        assert_eq!(simulation.num_particles(), 1);
+        assert_eq!(
+            simulation.validate_type_data(&ParticleTypeData::new_random(2, 1.0)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn validate_finds_a_bad_bucket_size() {
+        let type_data = ParticleTypeData::new_random(2, 1.0);
+        for bucket_size in [0.0, -5.0, f64::INFINITY] {
+            assert_eq!(
+                ParticleSimulation::validate(bucket_size, [2, 2], &type_data, &[], None),
+                Err(InvalidSimulation {
+                    violations: vec![Violation::BucketSize(bucket_size)]
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn validate_finds_zero_buckets() {
+        let type_data = ParticleTypeData::new_random(2, 1.0);
+        let result = ParticleSimulation::validate(100.0, [3, 0], &type_data, &[], None);
+        assert_eq!(
+            result.unwrap_err().violations,
+            [Violation::ZeroBuckets([3, 0])]
+        );
+        // The builder checks too, in stead of making a simulation that panics when spawning
+        let result = ParticleSimulation::builder()
+            .buckets([0, 0])
+            .random_particles(10)
+            .try_build();
+        assert_eq!(
+            result.unwrap_err().violations,
+            [Violation::ZeroBuckets([0, 0])]
+        );
+    }
+
+    #[test]
+    fn validate_finds_a_missing_particle() {
+        let particles = [
+            Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+            Particle::new([60.0, 50.0], [0.0, 0.0], 0),
+        ];
+        let type_data = ParticleTypeData::new_random(1, 1.0);
+        let result = ParticleSimulation::validate(100.0, [2, 2], &type_data, &particles, Some(3));
+        assert_eq!(
+            result.unwrap_err().violations,
+            [Violation::ParticleCount {
+                expected: 3,
+                actual: 2
+            }]
+        );
+        assert_eq!(
+            ParticleSimulation::validate(100.0, [2, 2], &type_data, &particles, Some(2)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn validate_finds_mismatched_attractions() {
+        let mut type_data = ParticleTypeData::new_random(3, 1.0);
+        type_data.colors = type_data.colors[..2].into();
+        let result = ParticleSimulation::validate(100.0, [2, 2], &type_data, &[], None);
+        assert_eq!(
+            result.unwrap_err().violations,
+            [Violation::AttractionSize {
+                size: [3, 3],
+                num_colors: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn validate_finds_missing_types() {
+        let particles = vec![
+            Particle::new([50.0, 50.0], [0.0, 0.0], 1),
+            Particle::new([60.0, 50.0], [0.0, 0.0], 4),
+        ];
+        let result = ParticleSimulation::try_with_particles(
+            100.0,
+            [2, 2],
+            ParticleSimulationParams::default(),
+            ParticleTypeData::new_random(2, 1.0),
+            particles.clone(),
+            None,
+        );
+        assert_eq!(
+            result.unwrap_err().violations,
+            [Violation::ParticleType { index: 1, typ: 4 }]
+        );
+
+        // Type data with fewer types than the particles use can't replace the current type data
+        let simulation = ParticleSimulation::with_particles(
+            100.0,
+            [2, 2],
+            ParticleSimulationParams::default(),
+            ParticleTypeData::new_random(5, 1.0),
+            particles,
+        );
+        let result = simulation.validate_type_data(&ParticleTypeData::new_random(3, 1.0));
+        assert_eq!(result.unwrap_err().violations.len(), 1);
+    }
+
+    #[test]
+    fn validate_finds_non_finite_particles() {
+        let particles = [
+            Particle::new([f64::NAN, 50.0], [0.0, 0.0], 0),
+            Particle::new([50.0, 50.0], [0.0, 0.0], 0),
+            Particle::new([50.0, 50.0], [0.0, f64::INFINITY], 0),
+        ];
+        let type_data = ParticleTypeData::new_random(1, 1.0);
+        let result = ParticleSimulation::validate(100.0, [2, 2], &type_data, &particles, Some(3));
+        assert_eq!(
+            result.unwrap_err().violations,
+            [
+                Violation::NonFinite { index: 0 },
+                Violation::NonFinite { index: 2 }
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_lists_every_violation() {
+        let mut type_data = ParticleTypeData::new_random(2, 1.0);
+        type_data.colors = ParticleTypeData::new_random(3, 1.0).colors;
+        let particles = [Particle::new([f64::NAN, 50.0], [0.0, 0.0], 7)];
+        let error =
+            ParticleSimulation::validate(0.0, [0, 1], &type_data, &particles, Some(4)).unwrap_err();
+        assert_eq!(error.violations.len(), 6);
+        assert_eq!(error.to_string().lines().count(), 6);
+    }
+
+    #[test]
+    fn smoothing_a_constant_matrix_changes_nothing() {
+        for amount in [0.25, 1.0] {
+            let mut type_data = ParticleTypeData::new_from_fn(4, 1.0, |_| 0.3);
+            type_data.smooth(amount);
+            assert!(type_data.base_attractions().data.iter().all(|&x| x == 0.3));
+            type_data.smooth_wrapping(amount);
+            assert!(type_data.base_attractions().data.iter().all(|&x| x == 0.3));
+        }
+    }
+
+    #[test]
+    fn smoothing_moves_attractions_to_their_neighbors_mean() {
+        #[rustfmt::skip]
+        let attractions = [
+            0.0, 0.2, 0.4,
+            0.6, 1.0, -0.2,
+            -1.0, 0.8, 0.0,
+        ];
+        let mut type_data = ParticleTypeData::new_from_fn(3, 1.0, |[source, target]| {
+            attractions[source * 3 + target]
+        });
+        type_data.smooth(1.0);
+        let at = |source: usize, target: usize| type_data.base_attractions()[[source, target]];
+        // The center has four neighbors and the corners two
+        assert!((at(1, 1) - (0.2 + 0.6 - 0.2 + 0.8) / 4.0).abs() < 1e-12);
+        assert!((at(0, 0) - (0.2 + 0.6) / 2.0).abs() < 1e-12);
+        assert!((at(0, 1) - (0.0 + 0.4 + 1.0) / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn morph_reaches_its_target_at_an_even_pace() {
+        let mut type_data = ParticleTypeData::new_from_fn(2, 1.0, |_| 0.0);
+        let target = Matrix::from_element([2, 2], 1.0);
+        let mut morph = AttractionMorph {
+            target: target.clone(),
+            steps_left: 4,
+        };
+        for step in 1..=4 {
+            assert_eq!(morph.step(&mut type_data), step == 4);
+            for &attraction in type_data.base_attractions().data.iter() {
+                assert!((attraction - step as f64 / 4.0).abs() < 1e-12);
+            }
+        }
+        assert_eq!(type_data.base_attractions().data, target.data);
+        assert!(morph.step(&mut type_data));
+
+        let mut morph = AttractionMorph {
+            target: Matrix::from_element([3, 3], 1.0),
+            steps_left: 4,
+        };
+        assert!(morph.step(&mut type_data));
+        assert_eq!(type_data.base_attractions().data, target.data);
+    }
+
+    #[test]
+    fn heavy_smoothing_converges_to_the_mean() {
+        let original = ParticleTypeData::new_random(5, 1.0);
+        let mean = |type_data: &ParticleTypeData| {
+            type_data.base_attractions().data.iter().sum::<f64>() / 25.0
+        };
+        let spread = |type_data: &ParticleTypeData| {
+            let data = &type_data.base_attractions().data;
+            data.iter().copied().fold(f64::MIN, f64::max)
+                - data.iter().copied().fold(f64::MAX, f64::min)
+        };
+
+        // Wrapping averages every attraction equally often, so the mean stays the same
+        let mut type_data = original.clone();
+        for _ in 0..500 {
+            type_data.smooth_wrapping(0.5);
+        }
+        assert!(spread(&type_data) < 1e-9);
+        assert!((mean(&type_data) - mean(&original)).abs() < 1e-9);
+
+        // Without wrapping the edges are weighted differently, so it only ends up close to it
+        let mut type_data = original.clone();
+        for _ in 0..500 {
+            type_data.smooth(0.5);
+        }
+        assert!(spread(&type_data) < 1e-9);
+        assert!((mean(&type_data) - mean(&original)).abs() < 0.5);
+    }
+
+    #[test]
+    fn bucket_size_can_be_read_back() {
+        let simulation = ParticleSimulation::builder().bucket_size(37.5).build();
+        assert_eq!(simulation.bucket_size(), 37.5);
     }
 }
@@ -0,0 +1,659 @@
+use super::{EdgeType, Particle, ParticleSimulation, ParticleTypeData, PARTICLE_RADIUS};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Particles closer than this are considered part of the same cluster.
+pub const CLUSTER_DISTANCE: f64 = PARTICLE_RADIUS * 3.0;
+
+/// `is_crowded` is true when one bucket holds more than this fraction of the particles.
+pub const CROWDED_FRACTION: f64 = 0.25;
+/// Buckets with fewer particles than this never count as crowded, since they are cheap to
+/// simulate either way.
+pub const CROWDED_MIN_PARTICLES: usize = 500;
+
+impl ParticleSimulation {
+    /// Counts groups of particles that are connected by chains of particles within
+    /// `max_distance` of each other. Isolated particles count as their own cluster.
+    ///
+    /// `max_distance` should be no larger than the interaction range, since only the buckets that
+    /// can hold particles within it are searched. With wrapping edges, clusters can connect across the edges.
+    pub fn cluster_count(&self, max_distance: f64) -> usize {
+        // Index of the first particle of each bucket
+        let mut offsets = Vec::with_capacity(self.buckets.data.len());
+        let mut num_particles = 0;
+        for bucket in self.buckets.data.iter() {
+            offsets.push(num_particles);
+            num_particles += bucket.len();
+        }
+        let offset_of = |index: [usize; 2]| offsets[index[0] + index[1] * self.buckets.size[0]];
+
+        let mut parents: Vec<usize> = (0..num_particles).collect();
+        fn root(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            i
+        }
+
+        let wrapping = matches!(self.params.edge_type, EdgeType::Wrapping);
+        let size = self.buckets.size;
+        let neighbor_offsets = self.neighbor_offsets();
+
+        let mut clusters = num_particles;
+        for bucket_x in 0..self.buckets.size[0] {
+            for bucket_y in 0..self.buckets.size[1] {
+                let bucket_index = [bucket_x, bucket_y];
+                let bucket = &self.buckets[bucket_index];
+                let offset = offset_of(bucket_index);
+
+                let neighbors = neighbor_offsets.iter().filter_map(|relative_index| {
+                    if wrapping {
+                        return Some([0, 1].map(|axis| {
+                            (bucket_index[axis] as isize + relative_index[axis])
+                                .rem_euclid(size[axis] as isize)
+                                as usize
+                        }));
+                    }
+                    let index = [
+                        bucket_x.checked_add_signed(relative_index[0])?,
+                        bucket_y.checked_add_signed(relative_index[1])?,
+                    ];
+                    self.buckets.check_index_bounds(index)?;
+                    Some(index)
+                });
+
+                // On grids only a few buckets wide, wrapping maps several neighbors (or a
+                // neighbor and the bucket itself) to the same bucket, which only needs searching
+                // once
+                let mut other_indices = vec![bucket_index];
+                for index in neighbors {
+                    if !other_indices.contains(&index) {
+                        other_indices.push(index);
+                    }
+                }
+
+                for other_index in other_indices {
+                    let other_bucket = &self.buckets[other_index];
+                    let other_offset = offset_of(other_index);
+                    for (i, particle) in bucket.iter().enumerate() {
+                        for (j, other) in other_bucket.iter().enumerate() {
+                            let delta = self.delta_wrapped(particle.position, other.position);
+                            if delta[0].powi(2) + delta[1].powi(2) > max_distance.powi(2) {
+                                continue;
+                            }
+
+                            let a = root(&mut parents, offset + i);
+                            let b = root(&mut parents, other_offset + j);
+                            if a != b {
+                                parents[a] = b;
+                                clusters -= 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        clusters
+    }
+
+    /// The shannon entropy of how particles are distributed between buckets, divided by its
+    /// maximum so that 1 means evenly spread and 0 means all particles are in one bucket.
+    pub fn spatial_entropy(&self) -> f64 {
+        let num_particles = self.num_particles();
+        let num_buckets = self.buckets.data.len();
+        if num_particles == 0 || num_buckets < 2 {
+            return 0.0;
+        }
+
+        let entropy: f64 = self
+            .buckets
+            .data
+            .iter()
+            .filter(|bucket| !bucket.is_empty())
+            .map(|bucket| {
+                let probability = bucket.len() as f64 / num_particles as f64;
+                -probability * probability.ln()
+            })
+            .sum();
+        entropy / (num_buckets as f64).ln()
+    }
+
+    /// The mean and variance of the kinetic energy of each particle, treating every particle as
+    /// having a mass of 1.
+    pub fn kinetic_energy_stats(&self) -> (f64, f64) {
+        let num_particles = self.num_particles();
+        if num_particles == 0 {
+            return (0.0, 0.0);
+        }
+
+        let energy = |velocity: [f64; 2]| (velocity[0].powi(2) + velocity[1].powi(2)) / 2.0;
+        let mean = self
+            .particles()
+            .map(|particle| energy(particle.velocity))
+            .sum::<f64>()
+            / num_particles as f64;
+        let variance = self
+            .particles()
+            .map(|particle| (energy(particle.velocity) - mean).powi(2))
+            .sum::<f64>()
+            / num_particles as f64;
+        (mean, variance)
+    }
+
+    /// An estimate of the memory used by the simulation in bytes, counting the capacity of each
+    /// bucket rather than the number of particles in it. Allocator overhead isn't included.
+    pub fn memory_usage(&self) -> usize {
+        let buckets: usize = self
+            .buckets
+            .data
+            .iter()
+            .map(|bucket| size_of::<Vec<Particle>>() + bucket.capacity() * size_of::<Particle>())
+            .sum();
+        let type_data = size_of_val(&*self.type_data.base_attractions.data)
+            + size_of_val(&*self.type_data.colors);
+        let type_caps = self.type_caps.capacity() * size_of::<Option<usize>>();
+        size_of::<Self>() + buckets + type_data + type_caps
+    }
+
+    /// The number of particles in the fullest bucket.
+    pub fn max_bucket_occupancy(&self) -> usize {
+        self.buckets.data.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// Whether a single bucket holds a large part of the particles. Every particle in a bucket
+    /// interacts with every other one, so such a bucket takes up most of the time of each step
+    /// and can slow the simulation to a crawl.
+    pub fn is_crowded(&self) -> bool {
+        let max = self.max_bucket_occupancy();
+        max >= CROWDED_MIN_PARTICLES && max as f64 > self.num_particles() as f64 * CROWDED_FRACTION
+    }
+
+    /// The number of particles of each type, indexed by type.
+    pub fn type_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.type_data.num_types()];
+        for particle in self.particles() {
+            counts[particle.typ] += 1;
+        }
+        counts
+    }
+
+    /// A fingerprint of the particles, for checking that two runs or two versions of the code
+    /// ended up in exactly the same state. Two simulations with the same hash almost certainly
+    /// have the same particles.
+    ///
+    /// Each particle contributes the bits of its position and velocity and its type. Particles
+    /// are sorted by those values first, so the order particles are stored in doesn't matter, and
+    /// neither do their ids. Floats are compared bit for bit, except that -0 counts as 0. Type
+    /// data and parameters aren't included. The hash is FNV-1a, so it stays the same across
+    /// platforms and compiler versions.
+    pub fn state_hash(&self) -> u64 {
+        let bits = |x: f64| if x == 0.0 { 0 } else { x.to_bits() };
+        let mut particles: Vec<[u64; 5]> = self
+            .particles()
+            .map(|particle| {
+                [
+                    bits(particle.position[0]),
+                    bits(particle.position[1]),
+                    bits(particle.velocity[0]),
+                    bits(particle.velocity[1]),
+                    particle.typ as u64,
+                ]
+            })
+            .collect();
+        particles.sort_unstable();
+
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in particles
+            .iter()
+            .flatten()
+            .flat_map(|word| word.to_le_bytes())
+        {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
+    /// Compares this simulation to `other`, for example a copy of it that has been stepped. The
+    /// changes are what `other` has that this simulation doesn't.
+    ///
+    /// Movement is measured between particles with the same id in both simulations, using
+    /// `delta_wrapped` of this simulation, so particles that wrapped around an edge count as
+    /// having moved only the short way.
+    pub fn diff(&self, other: &Self) -> SimDiff {
+        let positions: HashMap<u64, [f64; 2]> = self
+            .particles()
+            .map(|particle| (particle.id(), particle.position))
+            .collect();
+        let mut matched_particles = 0;
+        let mut total_displacement = 0.0;
+        for particle in other.particles() {
+            if let Some(&position) = positions.get(&particle.id()) {
+                let delta = self.delta_wrapped(position, particle.position);
+                total_displacement += delta[0].hypot(delta[1]);
+                matched_particles += 1;
+            }
+        }
+
+        let counts = self.type_counts();
+        let other_counts = other.type_counts();
+        let num_types = counts.len().max(other_counts.len());
+        let count_of = |counts: &[usize], typ| counts.get(typ).copied().unwrap_or(0) as isize;
+
+        SimDiff {
+            particle_count_change: other.num_particles() as isize - self.num_particles() as isize,
+            type_count_changes: (0..num_types)
+                .map(|typ| count_of(&other_counts, typ) - count_of(&counts, typ))
+                .collect(),
+            matched_particles,
+            mean_displacement: (matched_particles > 0)
+                .then(|| total_displacement / matched_particles as f64),
+        }
+    }
+}
+
+/// A measurement that can be taken of a simulation repeatedly, for example to record how it
+/// changes over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// The mean and variance from `kinetic_energy_stats`.
+    #[default]
+    KineticEnergy,
+    /// `cluster_count` with `CLUSTER_DISTANCE`.
+    ClusterCount,
+    /// `type_counts`, with one value per type.
+    TypeCounts,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 3] = [Self::KineticEnergy, Self::ClusterCount, Self::TypeCounts];
+    pub const NAMES: [&'static str; 3] = ["Kinetic Energy", "Cluster Count", "Type Counts"];
+
+    /// The name of each value `sample` returns for a simulation with `num_types` types.
+    pub fn columns(self, num_types: usize) -> Vec<String> {
+        match self {
+            Self::KineticEnergy => vec!["mean".to_string(), "variance".to_string()],
+            Self::ClusterCount => vec!["clusters".to_string()],
+            Self::TypeCounts => (0..num_types).map(|typ| format!("type_{typ}")).collect(),
+        }
+    }
+
+    pub fn sample(self, simulation: &ParticleSimulation) -> Vec<f64> {
+        match self {
+            Self::KineticEnergy => {
+                let (mean, variance) = simulation.kinetic_energy_stats();
+                vec![mean, variance]
+            }
+            Self::ClusterCount => vec![simulation.cluster_count(CLUSTER_DISTANCE) as f64],
+            Self::TypeCounts => simulation
+                .type_counts()
+                .into_iter()
+                .map(|count| count as f64)
+                .collect(),
+        }
+    }
+}
+
+/// Finds particles that keep moving but stay in about the same place, such as particles circling
+/// each other in a predator-prey pair. Positions are recorded by id with `record`, once per
+/// step.
+///
+/// A particle counts as orbiting once it has been recorded `window` times, has travelled at least
+/// `min_path` over the window, and ended up no more than `max_drift` times that distance from
+/// where the window started. Movement is measured on its own rather than relative to other
+/// particles, so an orbiting pair that drifts along together as a whole isn't found.
+#[derive(Clone, Debug)]
+pub struct OrbitDetector {
+    /// The number of steps a particle is followed for.
+    pub window: usize,
+    /// The largest net distance moved over the window, as a fraction of the path length.
+    pub max_drift: f64,
+    pub min_path: f64,
+    tracks: HashMap<u64, OrbitTrack>,
+    orbiting: HashSet<u64>,
+}
+
+/// The recent movement of one particle.
+#[derive(Clone, Debug)]
+struct OrbitTrack {
+    last_position: [f64; 2],
+    /// The displacement of each step in the window, oldest first.
+    steps: VecDeque<[f64; 2]>,
+    /// The sum of `steps`.
+    net: [f64; 2],
+    /// The sum of the lengths of `steps`.
+    path: f64,
+}
+
+impl Default for OrbitDetector {
+    fn default() -> Self {
+        Self::new(60, 0.1, PARTICLE_RADIUS * 20.0)
+    }
+}
+
+impl OrbitDetector {
+    pub fn new(window: usize, max_drift: f64, min_path: f64) -> Self {
+        Self {
+            window,
+            max_drift,
+            min_path,
+            tracks: HashMap::new(),
+            orbiting: HashSet::new(),
+        }
+    }
+
+    /// Records where each particle is now and updates which particles are orbiting. Particles
+    /// that are no longer in the simulation are forgotten.
+    pub fn record(&mut self, simulation: &ParticleSimulation) {
+        let window = self.window.max(1);
+        let mut tracks = HashMap::with_capacity(simulation.num_particles());
+        self.orbiting.clear();
+        for particle in simulation.particles() {
+            let track = match self.tracks.remove(&particle.id()) {
+                Some(mut track) => {
+                    let step = simulation.delta_wrapped(track.last_position, particle.position);
+                    track.last_position = particle.position;
+                    track.push(step);
+                    while track.steps.len() > window {
+                        track.pop();
+                    }
+                    track
+                }
+                None => OrbitTrack {
+                    last_position: particle.position,
+                    steps: VecDeque::with_capacity(window),
+                    net: [0.0, 0.0],
+                    path: 0.0,
+                },
+            };
+
+            let drift = (track.net[0].powi(2) + track.net[1].powi(2)).sqrt();
+            if track.steps.len() == window
+                && track.path >= self.min_path
+                && drift <= track.path * self.max_drift
+            {
+                self.orbiting.insert(particle.id());
+            }
+            tracks.insert(particle.id(), track);
+        }
+        self.tracks = tracks;
+    }
+
+    /// The ids of the particles that were orbiting when last recorded.
+    pub fn orbiting(&self) -> &HashSet<u64> {
+        &self.orbiting
+    }
+
+    pub fn orbit_count(&self) -> usize {
+        self.orbiting.len()
+    }
+}
+
+impl OrbitTrack {
+    fn push(&mut self, step: [f64; 2]) {
+        self.net = [self.net[0] + step[0], self.net[1] + step[1]];
+        self.path += (step[0].powi(2) + step[1].powi(2)).sqrt();
+        self.steps.push_back(step);
+    }
+
+    fn pop(&mut self) {
+        if let Some(step) = self.steps.pop_front() {
+            self.net = [self.net[0] - step[0], self.net[1] - step[1]];
+            self.path -= (step[0].powi(2) + step[1].powi(2)).sqrt();
+        }
+    }
+}
+
+/// The differences between two simulations, from `ParticleSimulation::diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimDiff {
+    pub particle_count_change: isize,
+    /// The change in the number of particles of each type, indexed by type.
+    pub type_count_changes: Vec<isize>,
+    /// The number of particles with the same id in both simulations.
+    pub matched_particles: usize,
+    /// The mean distance that the matched particles moved, or `None` if none matched.
+    pub mean_displacement: Option<f64>,
+}
+
+impl std::fmt::Display for SimDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Particle count change: {:+}", self.particle_count_change)?;
+        for (typ, change) in self.type_count_changes.iter().enumerate() {
+            if *change != 0 {
+                writeln!(f, "Type {typ}: {change:+}")?;
+            }
+        }
+        writeln!(f, "Matched particles: {}", self.matched_particles)?;
+        match self.mean_displacement {
+            Some(displacement) => write!(f, "Mean displacement: {displacement:.3}"),
+            None => write!(f, "Mean displacement: none"),
+        }
+    }
+}
+
+/// Scores how interesting the current state of a simulation is, between 0 and 1. This is the
+/// product of three terms, each between 0 and 1:
+///
+/// - Structure: `1 - clusters / particles`, using `CLUSTER_DISTANCE`. This is 0 when no
+///   particles are near each other and approaches 1 as particles gather into fewer clusters.
+/// - Spread: `spatial_entropy`, which is 0 when everything has collapsed into one bucket.
+/// - Activity: `cv / (1 + cv)`, where `cv` is the coefficient of variation (standard deviation
+///   divided by mean) of the particles' kinetic energy. This is 0 when the simulation is
+///   frozen or when every particle moves the same way.
+///
+/// An empty simulation scores 0.
+pub fn interestingness(simulation: &ParticleSimulation) -> f64 {
+    let num_particles = simulation.num_particles();
+    if num_particles == 0 {
+        return 0.0;
+    }
+
+    let structure = 1.0 - simulation.cluster_count(CLUSTER_DISTANCE) as f64 / num_particles as f64;
+    let spread = simulation.spatial_entropy();
+    let (mean, variance) = simulation.kinetic_energy_stats();
+    let activity = if mean > 0.0 {
+        let cv = variance.sqrt() / mean;
+        cv / (1.0 + cv)
+    } else {
+        0.0
+    };
+
+    structure * spread * activity
+}
+
+/// Runs a copy of the simulation for `steps` steps with its current attractions and with
+/// `trials` random attraction matrices, returning whichever type data scored highest with
+/// `interestingness` along with its score.
+pub fn explore(
+    simulation: &ParticleSimulation,
+    steps: usize,
+    trials: usize,
+) -> (ParticleTypeData, f64) {
+    let mut best = None;
+    for trial in 0..=trials {
+        let mut candidate = simulation.clone();
+        if trial > 0 {
+            candidate.type_data.randomize_attractions();
+        }
+        candidate.step_n(steps);
+
+        let score = interestingness(&candidate);
+        if best
+            .as_ref()
+            .is_none_or(|&(_, best_score)| score > best_score)
+        {
+            best = Some((candidate.type_data, score));
+        }
+    }
+    best.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle_simulation::ParticleSimulationParams;
+
+    #[test]
+    fn cluster_count_on_narrow_wrapping_grids() {
+        let count = |buckets: [usize; 2], positions: &[[f64; 2]]| {
+            let particles = positions
+                .iter()
+                .map(|&position| Particle::new(position, [0.0, 0.0], 0))
+                .collect();
+            let simulation = ParticleSimulation::with_particles(
+                100.0,
+                buckets,
+                ParticleSimulationParams {
+                    edge_type: EdgeType::Wrapping,
+                    ..Default::default()
+                },
+                ParticleTypeData::new_random(1, 1.0),
+                particles,
+            );
+            simulation.cluster_count(20.0)
+        };
+
+        // On a 2 wide grid the buckets on either side of a bucket are the same bucket. The pair
+        // at the edges of the world connects across them, and the pair in the middle doesn't
+        // reach either.
+        let positions = [[5.0, 50.0], [195.0, 50.0], [90.0, 250.0], [110.0, 250.0]];
+        assert_eq!(count([2, 3], &positions), 2);
+        assert_eq!(count([2, 3], &positions[..2]), 1);
+        assert_eq!(count([2, 3], &[[5.0, 150.0], [150.0, 150.0]]), 2);
+
+        // On a 1 wide grid every horizontal neighbor is the bucket itself
+        assert_eq!(
+            count([1, 3], &[[5.0, 50.0], [95.0, 50.0], [50.0, 250.0]]),
+            2
+        );
+        assert_eq!(count([1, 3], &[[50.0, 5.0], [50.0, 295.0]]), 1);
+    }
+
+    #[test]
+    fn state_hash_is_canonical() {
+        let positions = [[20.0, 30.0], [150.0, 40.0], [-0.0, 120.0], [170.0, 170.0]];
+        let hash = |order: &[usize]| {
+            let particles = order
+                .iter()
+                .map(|&i| Particle::new(positions[i], [0.0, 1.0], i % 2))
+                .collect();
+            let mut simulation = ParticleSimulation::with_particles(
+                100.0,
+                [2, 2],
+                ParticleSimulationParams::default(),
+                ParticleTypeData::new_from_fn(2, 1.0, |_| 0.5),
+                particles,
+            );
+            let before = simulation.state_hash();
+            simulation.step_n(10);
+            (before, simulation.state_hash())
+        };
+
+        let (before, after) = hash(&[0, 1, 2, 3]);
+        assert_ne!(before, after);
+        // The same state hashes the same every time, whatever order the particles are stored in
+        assert_eq!(hash(&[0, 1, 2, 3]), (before, after));
+        assert_eq!(hash(&[3, 1, 0, 2]), (before, after));
+        assert_ne!(hash(&[0, 1, 2]).0, before);
+    }
+
+    #[test]
+    fn orbit_detector_finds_a_period_2_state() {
+        // Particle 0 swaps between two positions every step, particle 1 moves steadily to the
+        // right, and particle 2 stays still
+        let frame = |step: usize| {
+            let particles = vec![
+                Particle::new([50.0 + (step % 2) as f64 * 10.0, 50.0], [0.0, 0.0], 0),
+                Particle::new([10.0 + step as f64 * 10.0, 150.0], [0.0, 0.0], 0),
+                Particle::new([250.0, 250.0], [0.0, 0.0], 0),
+            ];
+            ParticleSimulation::with_particles(
+                100.0,
+                [3, 3],
+                ParticleSimulationParams::default(),
+                ParticleTypeData::new_random(1, 1.0),
+                particles,
+            )
+        };
+
+        let mut detector = OrbitDetector::new(4, 0.1, 20.0);
+        for step in 0..4 {
+            detector.record(&frame(step));
+            assert_eq!(detector.orbit_count(), 0);
+        }
+        for step in 4..10 {
+            detector.record(&frame(step));
+            assert_eq!(detector.orbiting(), &HashSet::from([0]));
+        }
+    }
+
+    #[test]
+    fn diff_against_a_stepped_copy() {
+        let simulation = ParticleSimulation::builder()
+            .buckets([5, 5])
+            .random_type_data(4, 5.0)
+            .seed(8)
+            .random_particles(300)
+            .build();
+        let mut stepped = simulation.clone();
+        stepped.step_simulation();
+
+        let diff = simulation.diff(&stepped);
+        assert_eq!(diff.particle_count_change, 0);
+        assert_eq!(diff.type_count_changes, vec![0; 4]);
+        assert_eq!(diff.matched_particles, 300);
+
+        let before: HashMap<u64, [f64; 2]> = simulation
+            .particles()
+            .map(|particle| (particle.id(), particle.position))
+            .collect();
+        let expected = stepped
+            .particles()
+            .map(|particle| {
+                let position = before[&particle.id()];
+                (particle.position[0] - position[0]).hypot(particle.position[1] - position[1])
+            })
+            .sum::<f64>()
+            / 300.0;
+        let displacement = diff.mean_displacement.unwrap();
+        assert!(displacement > 0.0);
+        assert!((displacement - expected).abs() < 1e-9);
+
+        let summary = diff.to_string();
+        assert!(summary.contains("Particle count change: +0"));
+        assert!(summary.contains("Matched particles: 300"));
+        assert!(summary.contains(&format!("Mean displacement: {displacement:.3}")));
+    }
+
+    #[test]
+    fn diff_only_matches_particles_with_the_same_id() {
+        let simulation = ParticleSimulation::with_particles(
+            100.0,
+            [2, 2],
+            Default::default(),
+            ParticleTypeData::new_random(2, 1.0),
+            vec![
+                Particle::new([10.0, 10.0], [0.0, 0.0], 0),
+                Particle::new([20.0, 20.0], [0.0, 0.0], 1),
+            ],
+        );
+        let mut other = simulation.clone();
+        other.edit_particles(|particle| particle.typ == 0);
+        other.edit_particles(|particle| {
+            particle.position[0] += 3.0;
+            particle.position[1] += 4.0;
+            true
+        });
+        other.insert_particle(Particle::new([150.0, 150.0], [0.0, 0.0], 1));
+
+        let diff = simulation.diff(&other);
+        assert_eq!(diff.particle_count_change, 0);
+        assert_eq!(diff.type_count_changes, vec![0, 0]);
+        assert_eq!(diff.matched_particles, 1);
+        assert_eq!(diff.mean_displacement, Some(5.0));
+
+        let empty = ParticleSimulation::builder().buckets([2, 2]).build();
+        assert_eq!(empty.diff(&simulation).mean_displacement, None);
+    }
+}
@@ -0,0 +1,205 @@
+use super::{
+    InvalidSimulation, Particle, ParticleSimulation, ParticleSimulationParams, ParticleTypeData,
+};
+
+/// Sets up a `ParticleSimulation` one setting at a time, from `ParticleSimulation::builder`.
+/// Anything that isn't set uses the same defaults as the app: a 30 by 20 grid of buckets of size
+/// 100, the default params, and no particles with 50 types of random attractions scaled by 5.
+#[derive(Clone, Debug)]
+pub struct ParticleSimulationBuilder {
+    bucket_size: f64,
+    buckets: [usize; 2],
+    params: ParticleSimulationParams,
+    /// Random type data is made from `num_types` and `attraction_scale` if this is `None`.
+    type_data: Option<ParticleTypeData>,
+    num_types: usize,
+    attraction_scale: f64,
+    particles: Vec<Particle>,
+    seed: Option<u64>,
+    spawn: Spawn,
+}
+
+/// The random particles added once the simulation is built.
+#[derive(Clone, Copy, Debug)]
+enum Spawn {
+    None,
+    Count(usize),
+    Density(f64),
+}
+
+impl Default for ParticleSimulationBuilder {
+    fn default() -> Self {
+        Self {
+            bucket_size: 100.0,
+            buckets: [30, 20],
+            params: ParticleSimulationParams::default(),
+            type_data: None,
+            num_types: 50,
+            attraction_scale: 5.0,
+            particles: Vec::new(),
+            seed: None,
+            spawn: Spawn::None,
+        }
+    }
+}
+
+impl ParticleSimulationBuilder {
+    pub fn bucket_size(mut self, bucket_size: f64) -> Self {
+        self.bucket_size = bucket_size;
+        self
+    }
+
+    /// The number of buckets along each axis.
+    pub fn buckets(mut self, buckets: [usize; 2]) -> Self {
+        self.buckets = buckets;
+        self
+    }
+
+    pub fn params(mut self, params: ParticleSimulationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn type_data(mut self, type_data: ParticleTypeData) -> Self {
+        self.type_data = Some(type_data);
+        self
+    }
+
+    /// Uses random attractions between `num_types` types, in stead of any type data set
+    /// before.
+    pub fn random_type_data(mut self, num_types: usize, attraction_scale: f64) -> Self {
+        self.type_data = None;
+        self.num_types = num_types;
+        self.attraction_scale = attraction_scale;
+        self
+    }
+
+    /// Particles to start with, which are handled like in `ParticleSimulation::with_particles`.
+    pub fn particles(mut self, particles: Vec<Particle>) -> Self {
+        self.particles = particles;
+        self
+    }
+
+    /// The seed for spawning and stepping. Without one, the seed is random.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Adds `count` particles at random positions once the simulation is built, in stead of any
+    /// density set before.
+    pub fn random_particles(mut self, count: usize) -> Self {
+        self.spawn = Spawn::Count(count);
+        self
+    }
+
+    /// Adds random particles until there are `density` particles per unit of area once the
+    /// simulation is built, in stead of any count set before.
+    pub fn density(mut self, density: f64) -> Self {
+        self.spawn = Spawn::Density(density);
+        self
+    }
+
+    /// Panics if the settings are invalid, see `try_build`.
+    pub fn build(self) -> ParticleSimulation {
+        self.try_build().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Builds the simulation, or returns every problem that `ParticleSimulation::validate` finds
+    /// with the settings and starting particles.
+    pub fn try_build(self) -> Result<ParticleSimulation, InvalidSimulation> {
+        let type_data = self
+            .type_data
+            .unwrap_or_else(|| ParticleTypeData::new_random(self.num_types, self.attraction_scale));
+        let mut simulation = ParticleSimulation::try_with_particles(
+            self.bucket_size,
+            self.buckets,
+            self.params,
+            type_data,
+            self.particles,
+            None,
+        )?;
+        if let Some(seed) = self.seed {
+            simulation.set_seed(seed);
+        }
+        match self.spawn {
+            Spawn::None => {}
+            Spawn::Count(count) => simulation.add_random_particles(count),
+            Spawn::Density(density) => simulation.fill_to_density(density),
+        }
+        Ok(simulation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle_simulation::EdgeType;
+
+    #[test]
+    fn unset_settings_use_the_defaults() {
+        let simulation = ParticleSimulation::builder().build();
+        assert_eq!(simulation.bucket_size(), 100.0);
+        assert_eq!(simulation.grid_size(), [30, 20]);
+        assert_eq!(simulation.type_data().num_types(), 50);
+        assert_eq!(simulation.type_data().positive_scale(), 5.0);
+        assert_eq!(simulation.num_particles(), 0);
+    }
+
+    #[test]
+    fn build_a_configured_simulation() {
+        let type_data = ParticleTypeData::new_random(3, 2.0);
+        let build = || {
+            ParticleSimulation::builder()
+                .bucket_size(50.0)
+                .buckets([4, 3])
+                .params(ParticleSimulationParams {
+                    edge_type: EdgeType::Wrapping,
+                    ..Default::default()
+                })
+                .type_data(type_data.clone())
+                .particles(vec![Particle::new([10.0, 10.0], [1.0, 0.0], 2)])
+                .seed(21)
+                .random_particles(40)
+                .build()
+        };
+
+        let simulation = build();
+        assert_eq!(simulation.bucket_size(), 50.0);
+        assert_eq!(simulation.size(), [200.0, 150.0]);
+        assert!(matches!(simulation.params.edge_type, EdgeType::Wrapping));
+        assert_eq!(
+            simulation.type_data().base_attractions().data,
+            type_data.base_attractions().data
+        );
+        assert_eq!(simulation.seed(), 21);
+        // The given particle, then the random ones
+        assert_eq!(simulation.num_particles(), 41);
+        assert!(simulation.particles().any(|particle| particle.id() == 0
+            && particle.position == [10.0, 10.0]
+            && particle.typ == 2));
+        // The seed makes the random particles the same every time
+        assert_eq!(build().state_hash(), simulation.state_hash());
+    }
+
+    #[test]
+    fn the_last_spawn_setting_wins() {
+        let simulation = ParticleSimulation::builder()
+            .buckets([2, 2])
+            .random_type_data(2, 1.0)
+            .random_particles(5)
+            .density(0.001)
+            .build();
+        assert_eq!(simulation.num_particles(), 40);
+
+        let simulation = ParticleSimulation::builder()
+            .buckets([2, 2])
+            .type_data(ParticleTypeData::new_random(9, 1.0))
+            .random_type_data(2, 1.0)
+            .density(0.001)
+            .random_particles(5)
+            .build();
+        assert_eq!(simulation.num_particles(), 5);
+        assert_eq!(simulation.type_data().num_types(), 2);
+    }
+}
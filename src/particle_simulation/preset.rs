@@ -0,0 +1,175 @@
+use super::ParticleTypeData;
+use crate::matrix::Matrix;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl ParticleTypeData {
+    /// Encodes the number of types, positive scale, base attractions, and negative scale as
+    /// base64, so they can be shared as text. Colors are not included.
+    pub fn to_preset_string(&self) -> String {
+        let mut bytes = Vec::with_capacity(20 + self.base_attractions.data.len() * 8);
+        bytes.extend_from_slice(&(self.num_types() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.positive_scale.to_le_bytes());
+        for attraction in self.base_attractions.data.iter() {
+            bytes.extend_from_slice(&attraction.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.negative_scale.to_le_bytes());
+        encode(&bytes)
+    }
+
+    /// Writes Rust code that recreates the number of types, scales, and base attractions with
+    /// `new_from_fn`, as statements that define `type_data`.
+    pub fn to_rust_snippet(&self) -> String {
+        let num_types = self.num_types();
+        let mut snippet = format!("let attractions: [f64; {}] = [\n", num_types.pow(2));
+        for row in self.base_attractions.data.chunks(num_types.max(1)) {
+            let row: Vec<_> = row.iter().map(|x| format!("{x:?}")).collect();
+            snippet += &format!("    {},\n", row.join(", "));
+        }
+        let separate_scales = self.negative_scale != self.positive_scale;
+        snippet += &format!(
+            "];\nlet {}type_data = particle_life::particle_simulation::ParticleTypeData::new_from_fn(\n    \
+             {num_types},\n    {:?},\n    |[source, target]| attractions[source + target * {num_types}],\n);\n",
+            if separate_scales { "mut " } else { "" },
+            self.positive_scale,
+        );
+        if separate_scales {
+            snippet += &format!("type_data.set_negative_scale({:?});\n", self.negative_scale);
+        }
+        snippet
+    }
+
+    /// Decodes a string made by `to_preset_string`, using the default colors. Presets from before
+    /// there were separate scales use the positive scale for both. Returns `None` if the string
+    /// isn't a valid preset.
+    pub fn from_preset_string(preset: &str) -> Option<Self> {
+        let bytes = decode(preset.trim())?;
+        let (num_types, rest) = bytes.split_first_chunk::<4>()?;
+        let (positive_scale, rest) = rest.split_first_chunk::<8>()?;
+        let num_types = u32::from_le_bytes(*num_types) as usize;
+        let positive_scale = f64::from_le_bytes(*positive_scale);
+        let attractions_length = num_types.checked_pow(2)?.checked_mul(8)?;
+        let (rest, negative_scale) = match rest.len().checked_sub(attractions_length)? {
+            0 => (rest, positive_scale),
+            8 => {
+                let (rest, negative_scale) = rest.split_last_chunk::<8>()?;
+                (rest, f64::from_le_bytes(*negative_scale))
+            }
+            _ => return None,
+        };
+
+        let attractions: Vec<f64> = rest
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        let mut type_data = Self::new_from_fn(num_types, positive_scale, |_| 0.0);
+        type_data.negative_scale = negative_scale;
+        type_data.base_attractions = Matrix {
+            size: [num_types; 2],
+            data: attractions.into_boxed_slice(),
+        };
+        Some(type_data)
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    let mut string = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = [0, 1, 2].map(|i| chunk.get(i).copied().unwrap_or(0) as u32);
+        let group = group[0] << 16 | group[1] << 8 | group[2];
+        for i in 0..4 {
+            if i <= chunk.len() {
+                string.push(ALPHABET[(group >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                string.push('=');
+            }
+        }
+    }
+    string
+}
+
+fn decode(string: &str) -> Option<Vec<u8>> {
+    let string = string.as_bytes();
+    if !string.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(string.len() / 4 * 3);
+    let num_chunks = string.len() / 4;
+    for (index, chunk) in string.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || padding > 0 && index + 1 < num_chunks {
+            return None;
+        }
+        let mut group = 0;
+        for &c in &chunk[..4 - padding] {
+            let value = ALPHABET.iter().position(|&a| a == c)? as u32;
+            group = group << 6 | value;
+        }
+        group <<= 6 * padding;
+        bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle_simulation::ParticleSimulation;
+
+    #[test]
+    fn preset_string_round_trip() {
+        let mut type_data = ParticleTypeData::new_random(7, 3.5);
+        type_data.set_negative_scale(1.25);
+
+        let parsed = ParticleTypeData::from_preset_string(&type_data.to_preset_string()).unwrap();
+        assert_eq!(parsed.num_types(), 7);
+        assert_eq!(parsed.positive_scale(), 3.5);
+        assert_eq!(parsed.negative_scale(), 1.25);
+        assert_eq!(
+            parsed.base_attractions().data,
+            type_data.base_attractions().data
+        );
+    }
+
+    #[test]
+    fn step_a_300_type_preset() {
+        let preset = ParticleTypeData::new_random(300, 5.0).to_preset_string();
+        let type_data = ParticleTypeData::from_preset_string(&preset).unwrap();
+        assert_eq!(type_data.num_types(), 300);
+        assert_eq!(type_data.colors().len(), 300);
+
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([10, 10])
+            .type_data(type_data)
+            .random_particles(3000)
+            .build();
+        simulation.step_n(5);
+        assert_eq!(simulation.num_particles(), 3000);
+        let type_counts = simulation.type_counts();
+        assert_eq!(type_counts.len(), 300);
+        assert_eq!(type_counts.iter().sum::<usize>(), 3000);
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"Man"), "TWFu");
+        assert_eq!(encode(b"Ma"), "TWE=");
+        assert_eq!(encode(b"M"), "TQ==");
+        for length in 0..10 {
+            let bytes: Vec<u8> = (0..length).map(|i| (i * 97 + 200) as u8).collect();
+            assert_eq!(decode(&encode(&bytes)), Some(bytes));
+        }
+    }
+
+    #[test]
+    fn malformed_base64_is_rejected() {
+        assert_eq!(decode("TWF"), None);
+        assert_eq!(decode("TW!u"), None);
+        assert_eq!(decode("T==="), None);
+        assert_eq!(decode("TQ==TWFu"), None);
+        assert_eq!(decode("TW=u"), None);
+        assert!(ParticleTypeData::from_preset_string("TWFu").is_none());
+    }
+}
@@ -0,0 +1,126 @@
+use super::{EdgeType, ParticleSimulation, ParticleSimulationParams, ParticleTypeData};
+
+const BUCKET_SIZE: f64 = 100.0;
+const BUCKETS: [usize; 2] = [30, 20];
+
+/// A ready made simulation that shows off one of the classic kinds of particle life behavior.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scenario {
+    /// Round blobs of mostly one type, held together by attraction within the type and kept
+    /// apart by repulsion between types far apart on the color wheel.
+    #[default]
+    Cells,
+    /// Each type chases the next one and flees from the previous one, which pulls the types
+    /// into long chains that crawl around.
+    Worms,
+    /// A pair of types where one chases the other and the other flees, so they circle each
+    /// other in stead of settling down.
+    Orbits,
+    /// Types that repel themselves but attract each other, which packs them into alternating
+    /// lattices.
+    Crystals,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 4] = [Self::Cells, Self::Worms, Self::Orbits, Self::Crystals];
+    pub const NAMES: [&'static str; 4] = ["Cells", "Worms", "Orbits", "Crystals"];
+
+    /// Creates the simulation for this scenario, with its own attractions, edges, and starting
+    /// particles. Particle positions are random, so each call gives a different simulation.
+    pub fn build(self) -> ParticleSimulation {
+        let (edge_type, type_data) = match self {
+            Self::Cells => (
+                EdgeType::Wrapping,
+                ParticleTypeData::new_gradient(6, 5.0, 1.0),
+            ),
+            Self::Worms => {
+                let num_types = 5;
+                let type_data = ParticleTypeData::new_from_fn(num_types, 5.0, |[source, target]| {
+                    if target == source {
+                        0.5
+                    } else if target == (source + 1) % num_types {
+                        1.0
+                    } else if source == (target + 1) % num_types {
+                        -0.5
+                    } else {
+                        0.0
+                    }
+                });
+                (EdgeType::Wrapping, type_data)
+            }
+            Self::Orbits => {
+                #[rustfmt::skip]
+                let attractions = [
+                    -0.2, 1.0,
+                    -1.0, 0.5,
+                ];
+                let type_data = ParticleTypeData::new_from_fn(2, 5.0, |[source, target]| {
+                    attractions[target + source * 2]
+                });
+                let edge_type = EdgeType::Bouncing {
+                    multiplier: 1.0,
+                    pushback: 2.5,
+                };
+                (edge_type, type_data)
+            }
+            Self::Crystals => {
+                let type_data = ParticleTypeData::new_from_fn(3, 2.0, |[source, target]| {
+                    if source == target {
+                        -0.5
+                    } else {
+                        1.0
+                    }
+                });
+                let edge_type = EdgeType::Bouncing {
+                    multiplier: 0.5,
+                    pushback: 2.5,
+                };
+                (edge_type, type_data)
+            }
+        };
+
+        let mut simulation = ParticleSimulation::builder()
+            .bucket_size(BUCKET_SIZE)
+            .buckets(BUCKETS)
+            .params(ParticleSimulationParams {
+                edge_type,
+                ..Default::default()
+            })
+            .type_data(type_data)
+            .build();
+
+        let area = (BUCKET_SIZE * BUCKETS[0] as f64) * (BUCKET_SIZE * BUCKETS[1] as f64);
+        match self {
+            Self::Cells => simulation.add_random_particles((area * 1e-3) as usize),
+            Self::Worms => simulation.add_clustered_particles(1000, 300.0),
+            Self::Orbits => {
+                let center = BUCKETS.map(|buckets| buckets as f64 * BUCKET_SIZE / 2.0);
+                simulation.add_particles_in_circle(center, 800.0, (area * 1e-3) as usize);
+            }
+            Self::Crystals => simulation.add_particles_in_grid(25.0),
+        }
+        simulation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_scenario_builds_and_steps() {
+        for (scenario, name) in Scenario::ALL.into_iter().zip(Scenario::NAMES) {
+            let mut simulation = scenario.build();
+            let num_particles = simulation.num_particles();
+            assert!(num_particles > 0, "{name} has no particles");
+            simulation.step_n(3);
+            assert_eq!(simulation.step_count(), 3);
+            assert!(
+                simulation
+                    .particles()
+                    .all(|particle| particle.position.iter().all(|x| x.is_finite())),
+                "{name} has particles that aren't finite"
+            );
+        }
+    }
+}
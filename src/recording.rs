@@ -0,0 +1,109 @@
+use particle_life::particle_simulation::{analysis::Metric, ParticleSimulation};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+pub const RECORDING_PATH: &str = "particle_life_metrics.csv";
+
+/// Rows are flushed to the file after this many are written, so a crash loses at most this many.
+const FLUSH_INTERVAL: usize = 16;
+
+/// Writes a metric of the simulation to a csv file every `interval` steps, with the step in the
+/// first column.
+pub struct MetricRecorder {
+    pub metric: Metric,
+    pub interval: usize,
+    writer: BufWriter<File>,
+    step: usize,
+    rows: usize,
+}
+
+impl MetricRecorder {
+    /// Creates the file, replacing any earlier recording, and writes the header for the columns
+    /// of `metric` with `num_types` types.
+    pub fn new(metric: Metric, interval: usize, num_types: usize) -> io::Result<Self> {
+        Self::with_path(RECORDING_PATH, metric, interval, num_types)
+    }
+
+    /// Like `new`, but records to `path` in stead of `RECORDING_PATH`.
+    pub fn with_path(
+        path: impl AsRef<Path>,
+        metric: Metric,
+        interval: usize,
+        num_types: usize,
+    ) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "step,{}", metric.columns(num_types).join(","))?;
+        writer.flush()?;
+        Ok(Self {
+            metric,
+            interval: interval.max(1),
+            writer,
+            step: 0,
+            rows: 0,
+        })
+    }
+
+    /// Called once per step, with the simulation before the step is taken.
+    pub fn record(&mut self, simulation: &ParticleSimulation) -> io::Result<()> {
+        if self.step.is_multiple_of(self.interval) {
+            let values: Vec<_> = self
+                .metric
+                .sample(simulation)
+                .iter()
+                .map(f64::to_string)
+                .collect();
+            writeln!(self.writer, "{},{}", self.step, values.join(","))?;
+            self.rows += 1;
+            if self.rows.is_multiple_of(FLUSH_INTERVAL) {
+                self.writer.flush()?;
+            }
+        }
+        self.step += 1;
+        Ok(())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn records_every_interval() {
+        let path = std::env::temp_dir().join(format!(
+            "particle_life_recording_test_{}.csv",
+            std::process::id()
+        ));
+        let mut simulation = ParticleSimulation::builder()
+            .buckets([3, 3])
+            .random_type_data(2, 1.0)
+            .random_particles(40)
+            .build();
+
+        let mut recorder = MetricRecorder::with_path(&path, Metric::TypeCounts, 3, 2).unwrap();
+        for _ in 0..10 {
+            recorder.record(&simulation).unwrap();
+            simulation.step_simulation();
+        }
+        assert_eq!(recorder.rows(), 4);
+        drop(recorder);
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "step,type_0,type_1");
+        assert_eq!(lines.len(), 5);
+        for (line, step) in lines[1..].iter().zip([0, 3, 6, 9]) {
+            let values: Vec<f64> = line.split(',').map(|x| x.parse().unwrap()).collect();
+            assert_eq!(values[0], step as f64);
+            assert_eq!(values[1] + values[2], 40.0);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use macroquad::math::{vec2, Vec2};
+use std::{fs, io};
+
+pub const SESSION_PATH: &str = "particle_life_session.txt";
+
+/// The newest version of the session file. Files from older versions are read with the default
+/// for anything they don't contain.
+const VERSION: u32 = 1;
+
+/// What the app was showing, so it can be restored later. Stored in the same `key = value` format
+/// as `AppSettings`, starting with a `version` key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub camera_target: Vec2,
+    /// The vertical zoom of the camera. The horizontal zoom follows from the window's aspect
+    /// ratio.
+    pub camera_zoom: f32,
+    pub follow_center_of_mass: bool,
+    pub debug_mode: u8,
+    pub info_window_open: bool,
+    pub brush_type: Option<usize>,
+    /// The attractions, as a string from `ParticleTypeData::to_preset_string`.
+    pub attractions: Option<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            camera_target: Vec2::ZERO,
+            camera_zoom: 1.0,
+            follow_center_of_mass: false,
+            debug_mode: 0,
+            info_window_open: false,
+            brush_type: None,
+            attractions: None,
+        }
+    }
+}
+
+impl Session {
+    /// Loads the session file. Returns `None` if there is no session file or it was written by
+    /// a newer version.
+    pub fn load() -> Option<Self> {
+        Self::parse(&fs::read_to_string(SESSION_PATH).ok()?)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(SESSION_PATH, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut session = Self::default();
+        let mut version = None;
+        for line in text.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                let (key, value) = (key.trim(), value.trim());
+                if key == "version" {
+                    version = value.parse::<u32>().ok();
+                } else {
+                    session.set(key, value);
+                }
+            }
+        }
+        version.filter(|&version| version <= VERSION)?;
+        Some(session)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            "camera_target" => {
+                let (x, y) = value.split_once(' ')?;
+                self.camera_target = vec2(x.parse().ok()?, y.parse().ok()?);
+            }
+            "camera_zoom" => {
+                let zoom: f32 = value.parse().ok()?;
+                self.camera_zoom = (zoom.is_finite() && zoom > 0.0).then_some(zoom)?;
+            }
+            "follow_center_of_mass" => self.follow_center_of_mass = value.parse().ok()?,
+            "debug_mode" => self.debug_mode = value.parse().ok()?,
+            "info_window_open" => self.info_window_open = value.parse().ok()?,
+            "brush_type" => self.brush_type = Some(value.parse().ok()?),
+            "attractions" => self.attractions = Some(value.to_string()),
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl std::fmt::Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "version = {VERSION}")?;
+        writeln!(
+            f,
+            "camera_target = {} {}",
+            self.camera_target.x, self.camera_target.y
+        )?;
+        writeln!(f, "camera_zoom = {}", self.camera_zoom)?;
+        writeln!(f, "follow_center_of_mass = {}", self.follow_center_of_mass)?;
+        writeln!(f, "debug_mode = {}", self.debug_mode)?;
+        writeln!(f, "info_window_open = {}", self.info_window_open)?;
+        if let Some(brush_type) = self.brush_type {
+            writeln!(f, "brush_type = {brush_type}")?;
+        }
+        if let Some(attractions) = &self.attractions {
+            writeln!(f, "attractions = {attractions}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_round_trip() {
+        let session = Session {
+            camera_target: vec2(1234.5, -0.125),
+            camera_zoom: 0.0012345,
+            follow_center_of_mass: true,
+            debug_mode: 2,
+            info_window_open: true,
+            brush_type: Some(3),
+            attractions: Some("AAAA".to_string()),
+        };
+        let parsed = Session::parse(&session.to_string()).unwrap();
+        assert_eq!(parsed.camera_target, session.camera_target);
+        assert_eq!(parsed.camera_zoom, session.camera_zoom);
+        assert_eq!(parsed, session);
+    }
+
+    #[test]
+    fn missing_keys_use_defaults() {
+        assert_eq!(Session::parse("version = 1\n"), Some(Session::default()));
+        assert_eq!(Session::parse("camera_zoom = 2\n"), None);
+        assert_eq!(
+            Session::parse(&format!("version = {}\n", VERSION + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn malformed_zoom_uses_the_default() {
+        for zoom in ["0", "-1", "NaN", "inf", "zoom"] {
+            let session = Session::parse(&format!("version = 1\ncamera_zoom = {zoom}\n"));
+            assert_eq!(session, Some(Session::default()), "{zoom}");
+        }
+    }
+}
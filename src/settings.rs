@@ -0,0 +1,228 @@
+use crate::bloom::BloomSettings;
+use macroquad::color::{colors, Color};
+use particle_life::particle_simulation::{BorderStyle, DrawSettings, ParticleShape};
+use std::{fmt::Write, fs, io};
+
+pub const SETTINGS_PATH: &str = "particle_life_settings.txt";
+
+/// Settings that are saved between runs of the app. They are stored as one `key = value` pair per
+/// line, so that missing or unknown keys can be skipped.
+#[derive(Clone, Debug)]
+pub struct AppSettings {
+    pub draw_settings: DrawSettings,
+    pub bloom: BloomSettings,
+    pub background_color: Color,
+    /// The framerate to render at while paused and idle. Zero means unlimited.
+    pub idle_fps: f32,
+    /// The highest framerate to render at. Zero means unlimited.
+    pub fps_limit: f32,
+    /// The highest number of steps per second to simulate at. Zero means unlimited.
+    pub tps_limit: f32,
+    /// Pulls the camera back when its center is moved outside of the simulation.
+    pub camera_bounds: bool,
+    /// The zoom levels picked with the number keys, as the radius of a particle in pixels.
+    pub zoom_presets: [f32; 2],
+    /// Shows a scale bar and the position of the cursor in the bottom left corner.
+    pub show_scale_bar: bool,
+    /// Fits the view to the world again when the window changes size, unless the camera has
+    /// been moved or zoomed by hand since it was last fitted.
+    pub refit_on_resize: bool,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            draw_settings: DrawSettings::default(),
+            bloom: BloomSettings::default(),
+            background_color: colors::BLACK,
+            idle_fps: 10.0,
+            fps_limit: 0.0,
+            tps_limit: crate::TICK_RATE as f32,
+            camera_bounds: true,
+            zoom_presets: [2.0, 8.0],
+            show_scale_bar: false,
+            refit_on_resize: false,
+        }
+    }
+}
+
+impl AppSettings {
+    /// Loads the settings file, using the default for any settings that can't be read.
+    pub fn load() -> Self {
+        let mut settings = Self::default();
+        if let Ok(text) = fs::read_to_string(SETTINGS_PATH) {
+            for line in text.lines() {
+                if let Some((key, value)) = line.split_once('=') {
+                    settings.set(key.trim(), value.trim());
+                }
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(SETTINGS_PATH, self.to_string())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Option<()> {
+        let draw = &mut self.draw_settings;
+        match key {
+            "draw_border" => draw.draw_border = value.parse().ok()?,
+            "border_color" => draw.border_color = parse_color(value)?,
+            "border_thickness" => draw.border_thickness = value.parse().ok()?,
+            "border_style" => {
+                let index = BorderStyle::NAMES.iter().position(|&name| name == value)?;
+                draw.border_style = BorderStyle::ALL[index];
+            }
+            "border_corner_markers" => draw.border_corner_markers = value.parse().ok()?,
+            "draw_bucket_edges" => draw.draw_bucket_edges = value.parse().ok()?,
+            "draw_background_grid" => draw.draw_background_grid = value.parse().ok()?,
+            "background_grid_divisions" => draw.background_grid_divisions = value.parse().ok()?,
+            "background_grid_color" => draw.background_grid_color = parse_color(value)?,
+            "draw_interaction_range" => draw.draw_interaction_range = value.parse().ok()?,
+            "bucket_edge_color" => draw.bucket_edge_color = parse_color(value)?,
+            "line_thickness" => draw.line_thickness = value.parse().ok()?,
+            "draw_velocities" => draw.draw_velocities = value.parse().ok()?,
+            "velocity_scale" => draw.velocity_scale = value.parse().ok()?,
+            "draw_impulses" => draw.draw_impulses = value.parse().ok()?,
+            "impulse_scale" => draw.impulse_scale = value.parse().ok()?,
+            "particle_shape" => {
+                let index = ParticleShape::NAMES
+                    .iter()
+                    .position(|&name| name == value)?;
+                draw.particle_shape = ParticleShape::ALL[index];
+            }
+            "sort_particles_by_type" => draw.sort_particles_by_type = value.parse().ok()?,
+            "background_color" => self.background_color = parse_color(value)?,
+            "idle_fps" => self.idle_fps = value.parse().ok()?,
+            "fps_limit" => self.fps_limit = value.parse().ok()?,
+            "tps_limit" => self.tps_limit = parse_rate(value)?,
+            "camera_bounds" => self.camera_bounds = value.parse().ok()?,
+            "zoom_presets" => {
+                let (first, second) = value.split_once(' ')?;
+                self.zoom_presets = [first.parse().ok()?, second.parse().ok()?];
+            }
+            "show_scale_bar" => self.show_scale_bar = value.parse().ok()?,
+            "refit_on_resize" => self.refit_on_resize = value.parse().ok()?,
+            "bloom_enabled" => self.bloom.enabled = value.parse().ok()?,
+            "bloom_intensity" => self.bloom.intensity = value.parse().ok()?,
+            "bloom_threshold" => self.bloom.threshold = value.parse().ok()?,
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl std::fmt::Display for AppSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let draw = &self.draw_settings;
+        writeln!(f, "draw_border = {}", draw.draw_border)?;
+        writeln!(f, "border_color = {}", format_color(draw.border_color))?;
+        writeln!(f, "border_thickness = {}", draw.border_thickness)?;
+        writeln!(f, "border_style = {:?}", draw.border_style)?;
+        writeln!(f, "border_corner_markers = {}", draw.border_corner_markers)?;
+        writeln!(f, "draw_bucket_edges = {}", draw.draw_bucket_edges)?;
+        writeln!(f, "draw_background_grid = {}", draw.draw_background_grid)?;
+        writeln!(
+            f,
+            "background_grid_divisions = {}",
+            draw.background_grid_divisions
+        )?;
+        writeln!(
+            f,
+            "background_grid_color = {}",
+            format_color(draw.background_grid_color)
+        )?;
+        writeln!(
+            f,
+            "draw_interaction_range = {}",
+            draw.draw_interaction_range
+        )?;
+        writeln!(
+            f,
+            "bucket_edge_color = {}",
+            format_color(draw.bucket_edge_color)
+        )?;
+        writeln!(f, "line_thickness = {}", draw.line_thickness)?;
+        writeln!(f, "draw_velocities = {}", draw.draw_velocities)?;
+        writeln!(f, "velocity_scale = {}", draw.velocity_scale)?;
+        writeln!(f, "draw_impulses = {}", draw.draw_impulses)?;
+        writeln!(f, "impulse_scale = {}", draw.impulse_scale)?;
+        writeln!(f, "particle_shape = {:?}", draw.particle_shape)?;
+        writeln!(
+            f,
+            "sort_particles_by_type = {}",
+            draw.sort_particles_by_type
+        )?;
+        writeln!(
+            f,
+            "background_color = {}",
+            format_color(self.background_color)
+        )?;
+        writeln!(f, "idle_fps = {}", self.idle_fps)?;
+        writeln!(f, "fps_limit = {}", self.fps_limit)?;
+        writeln!(f, "tps_limit = {}", self.tps_limit)?;
+        writeln!(f, "camera_bounds = {}", self.camera_bounds)?;
+        writeln!(
+            f,
+            "zoom_presets = {} {}",
+            self.zoom_presets[0], self.zoom_presets[1]
+        )?;
+        writeln!(f, "show_scale_bar = {}", self.show_scale_bar)?;
+        writeln!(f, "refit_on_resize = {}", self.refit_on_resize)?;
+        writeln!(f, "bloom_enabled = {}", self.bloom.enabled)?;
+        writeln!(f, "bloom_intensity = {}", self.bloom.intensity)?;
+        writeln!(f, "bloom_threshold = {}", self.bloom.threshold)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn format_color(color: Color) -> String {
+    let mut text = String::new();
+    for component in [color.r, color.g, color.b, color.a] {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        write!(text, "{component}").unwrap();
+    }
+    text
+}
+
+/// Parses a rate like `tps_limit`, which is either a number that isn't negative or `unlimited`
+/// for 0. There's no upper bound.
+pub(crate) fn parse_rate(text: &str) -> Option<f32> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("unlimited") {
+        return Some(0.0);
+    }
+    let rate: f32 = text.parse().ok()?;
+    (rate.is_finite() && rate >= 0.0).then_some(rate)
+}
+
+pub(crate) fn parse_color(text: &str) -> Option<Color> {
+    let mut components = text.split_whitespace().map(|x| x.parse::<f32>());
+    let mut next = || components.next()?.ok();
+    Some(Color::new(next()?, next()?, next()?, next()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tps_limit_has_no_upper_bound() {
+        assert_eq!(parse_rate("1000"), Some(1000.0));
+        assert_eq!(parse_rate(" 12000.5 "), Some(12000.5));
+        assert_eq!(parse_rate("Unlimited"), Some(0.0));
+        assert_eq!(parse_rate("-5"), None);
+        assert_eq!(parse_rate("inf"), None);
+        assert_eq!(parse_rate("fast"), None);
+
+        let mut settings = AppSettings::default();
+        assert_eq!(settings.set("tps_limit", "1000"), Some(()));
+        assert_eq!(settings.tps_limit, 1000.0);
+        assert!(settings.to_string().contains("tps_limit = 1000\n"));
+        assert_eq!(settings.set("tps_limit", "-1"), None);
+        assert_eq!(settings.tps_limit, 1000.0);
+    }
+}
@@ -0,0 +1,2006 @@
+use crate::{
+    favorites::{Favorite, Favorites},
+    recording::{MetricRecorder, RECORDING_PATH},
+    settings::{self, AppSettings},
+    view, SimulationEdit, SimulationThreadData, TimeDilation, DEFAULT_GRID_SIZE, TICK_RATE,
+};
+use macroquad::{
+    camera::{self, Camera2D},
+    color::{colors, Color},
+    input::{self, KeyCode, MouseButton},
+    math::{vec2, Rect, Vec2},
+    miniquad, shapes, text,
+    texture::{FilterMode, Texture2D},
+    ui::{
+        hash, root_ui,
+        widgets::{InputText, Window},
+        Id, Ui,
+    },
+    window,
+};
+use particle_life::{
+    matrix::Matrix,
+    particle_simulation::{
+        analysis::{self, Metric, OrbitDetector},
+        scenario::Scenario,
+        AttractionMorph, BorderStyle, CoincidentParticles, NeighborSearch, ParticleShape,
+        ParticleSimulation, ParticleTypeData, RemovedTypes, PARTICLE_RADIUS,
+    },
+};
+use std::{
+    collections::HashSet,
+    ops::Range,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The size of the text fields in the info window, including their labels.
+const TEXT_FIELD_SIZE: Vec2 = vec2(290.0, 19.0);
+/// The fraction of a text field's width that `InputText` gives to the field, with the label
+/// after it.
+const TEXT_FIELD_RATIO: f32 = 0.5;
+
+/// How often the interestingness score in the info window is recalculated.
+const SCORE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The most types the types drag can be set to. The simulation itself has no limit, so presets
+/// and favorites can have more, and the drag won't shrink them unless it's moved down.
+const MAX_TYPES: u32 = 250;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpawnPattern {
+    Random,
+    Circle,
+    Ring,
+    Grid,
+    Clustered,
+    Poisson,
+}
+
+impl SpawnPattern {
+    pub const ALL: [SpawnPattern; 6] = [
+        Self::Random,
+        Self::Circle,
+        Self::Ring,
+        Self::Grid,
+        Self::Clustered,
+        Self::Poisson,
+    ];
+    pub const NAMES: [&'static str; 6] = [
+        "Random",
+        "Circle",
+        "Ring",
+        "Grid",
+        "Clustered",
+        "Spread Out",
+    ];
+}
+
+/// A request for the main loop to save or load the session, since the camera isn't available to
+/// the ui.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionAction {
+    Save,
+    Load,
+}
+
+pub struct UiState {
+    pub info_window_open: bool,
+    pub spawn_pattern: usize,
+    pub spawn_count: f32,
+    pub spawn_radius: f32,
+    pub spawn_spacing: f32,
+    pub spawn_per_type: f32,
+    pub spawn_spread: f32,
+    pub spawn_min_distance: f32,
+    pub cap_type: u32,
+    pub cap_count: u32,
+    pub fill_density: f32,
+    /// Spawning more particles than this at once asks for confirmation first.
+    pub confirmation_threshold: f32,
+    /// A tick rate typed in by hand, which can go past the end of the TPS slider.
+    pub custom_tps: String,
+    pub pending_spawn: Option<(usize, SimulationEdit)>,
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub settings_status: String,
+    pub session_action: Option<SessionAction>,
+    pub session_status: String,
+    pub edit_source: u32,
+    pub edit_target: u32,
+    pub diagonal_factor: f32,
+    pub smooth_amount: f32,
+    pub smooth_wrapping: bool,
+    pub show_source_row: bool,
+    /// Whether the scale and attraction sliders move along the logarithm of their value.
+    pub logarithmic_sliders: bool,
+    pub last_score: Option<(f64, Instant)>,
+    pub step_count: f32,
+    pub time_dilation: TimeDilation,
+    pub recording_metric: usize,
+    pub recording_interval: f32,
+    pub recording_status: String,
+    pub orbit_window: f32,
+    pub orbit_max_drift: f32,
+    pub orbit_min_path: f32,
+    /// The ids of the particles that were orbiting in the last update.
+    pub orbits: HashSet<u64>,
+    pub explore_steps: f32,
+    pub explore_trials: f32,
+    pub exploration: Option<Receiver<(ParticleTypeData, f64)>>,
+    pub explore_status: String,
+    pub morph_target: Option<Matrix<f64>>,
+    pub morph_steps: f32,
+    pub confirm_reset_all: bool,
+    pub preset_status: String,
+    pub gradient_falloff: f32,
+    pub jitter_amount: f32,
+    pub removed_types: RemovedTypes,
+    pub scenario: usize,
+    /// The type of particle spawned by the brush, or `None` for random types.
+    pub brush_type: Option<usize>,
+    /// The type picked by the first number key.
+    pub hotbar_offset: usize,
+    /// The ids of the selected particles.
+    pub selection: HashSet<u64>,
+    /// Where the selection box started in the world, while it is being dragged.
+    pub selection_start: Option<Vec2>,
+    pub selection_type: u32,
+    pub nudge_distance: f32,
+    pub favorites: Favorites,
+    pub favorite_name: String,
+    /// A heatmap of the attractions of each favorite, or `None` if they need to be remade.
+    pub favorite_thumbnails: Option<Vec<Texture2D>>,
+    pub favorites_status: String,
+    /// The colors the legend swatches were made from, and a swatch for each of them.
+    pub legend: Option<(Vec<Color>, Vec<Texture2D>)>,
+    /// Whether one of the text fields has keyboard focus, so that typing into it shouldn't set
+    /// off any hotkeys.
+    pub text_focus: bool,
+}
+
+impl Default for UiState {
+    fn default() -> Self {
+        Self {
+            info_window_open: false,
+            spawn_pattern: 0,
+            spawn_count: 1000.0,
+            spawn_radius: 500.0,
+            spawn_spacing: 20.0,
+            spawn_per_type: 20.0,
+            spawn_spread: 50.0,
+            spawn_min_distance: 20.0,
+            cap_type: 0,
+            cap_count: 100,
+            fill_density: 2e-3,
+            confirmation_threshold: 20000.0,
+            custom_tps: String::new(),
+            pending_spawn: None,
+            grid_width: DEFAULT_GRID_SIZE[0] as u32,
+            grid_height: DEFAULT_GRID_SIZE[1] as u32,
+            settings_status: String::new(),
+            session_action: None,
+            session_status: String::new(),
+            edit_source: 0,
+            edit_target: 0,
+            diagonal_factor: 1.5,
+            smooth_amount: 0.5,
+            smooth_wrapping: false,
+            show_source_row: false,
+            logarithmic_sliders: false,
+            last_score: None,
+            step_count: 1.0,
+            time_dilation: TimeDilation::default(),
+            recording_metric: 0,
+            recording_interval: 10.0,
+            recording_status: String::new(),
+            orbit_window: 60.0,
+            orbit_max_drift: 0.1,
+            orbit_min_path: 100.0,
+            orbits: HashSet::new(),
+            explore_steps: 300.0,
+            explore_trials: 10.0,
+            exploration: None,
+            explore_status: String::new(),
+            morph_target: None,
+            morph_steps: 300.0,
+            confirm_reset_all: false,
+            preset_status: String::new(),
+            gradient_falloff: 3.0,
+            jitter_amount: 0.1,
+            removed_types: RemovedTypes::Randomize,
+            scenario: 0,
+            brush_type: None,
+            hotbar_offset: 0,
+            selection: HashSet::new(),
+            selection_start: None,
+            selection_type: 0,
+            nudge_distance: 10.0,
+            favorites: Favorites::default(),
+            favorite_name: String::new(),
+            favorite_thumbnails: None,
+            legend: None,
+            text_focus: false,
+            favorites_status: String::new(),
+        }
+    }
+}
+
+impl UiState {
+    /// Puts every control back to its default and leaves the info window open. The favorites
+    /// and their thumbnails are kept, since they mirror the favorites file, and saving after
+    /// clearing them would delete every favorite from it.
+    pub fn reset(&mut self) {
+        let favorites = std::mem::take(&mut self.favorites);
+        let favorite_thumbnails = self.favorite_thumbnails.take();
+        *self = UiState {
+            info_window_open: true,
+            favorites,
+            favorite_thumbnails,
+            ..Default::default()
+        };
+    }
+}
+
+/// Draws the info window (if it is open) and queues any edits made with it.
+pub fn update_info_window(
+    state: &mut UiState,
+    settings: &mut AppSettings,
+    simulation: &ParticleSimulation,
+    thread_data: &mut SimulationThreadData,
+) {
+    // Apply the result of auto exploration, even if the window was closed while it ran
+    if let Some(receiver) = &state.exploration {
+        if let Ok((type_data, score)) = receiver.try_recv() {
+            state.exploration = None;
+            state.explore_status = format!("Best score: {score:.3}");
+            thread_data.edits.push(Box::new(move |simulation| {
+                *simulation.type_data_mut() = type_data;
+            }));
+        }
+    }
+
+    if !state.info_window_open {
+        state.text_focus = false;
+        return;
+    }
+
+    // Where each text field was drawn, to track which one has keyboard focus
+    let mut text_fields = Vec::new();
+    let size = vec2(320.0, 500.0);
+    let position = vec2(window::screen_width() - size.x - 10.0, 10.0);
+    let world_size = simulation.size();
+    let center = world_size.map(|x| x / 2.0);
+
+    Window::new(hash!(), position, size)
+        .label("Info")
+        .ui(&mut root_ui(), |ui| {
+            ui.label(None, &format!("Particles: {}", simulation.num_particles()));
+            ui.label(None, &format!("Step: {}", simulation.step_count()));
+            ui.label(
+                None,
+                &format!(
+                    "Memory: {:.1} MB",
+                    simulation.memory_usage() as f64 / 1_000_000.0
+                ),
+            );
+            let counting = simulation.interaction_count().is_some();
+            let mut new_counting = counting;
+            ui.checkbox(hash!(), "Count Interactions", &mut new_counting);
+            if new_counting != counting {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_count_interactions(new_counting)
+                }));
+            }
+            if let Some(stats) = simulation.step_stats() {
+                ui.label(
+                    None,
+                    &format!("Interactions per step: {}", stats.interactions),
+                );
+                ui.label(
+                    None,
+                    &format!("Kinetic energy: {:.1}", stats.kinetic_energy),
+                );
+            }
+            if thread_data.falling_behind {
+                ui.label(None, "Falling behind the tick rate");
+            }
+            if simulation.is_crowded() {
+                ui.label(
+                    None,
+                    &format!(
+                        "{} particles are in one bucket",
+                        simulation.max_bucket_occupancy()
+                    ),
+                );
+            }
+            if thread_data.steps > 0 {
+                ui.label(None, &format!("Stepping: {} steps left", thread_data.steps));
+                ui.same_line(0.0);
+                if ui.button(None, "Stop") {
+                    thread_data.steps = 0;
+                }
+            } else if !thread_data.active {
+                ui.slider(hash!(), "Steps", 1.0..1000.0, &mut state.step_count);
+                if ui.button(None, "Step") {
+                    thread_data.steps = state.step_count as usize;
+                }
+            }
+
+            let mut time_dilation = thread_data.time_dilation.is_some();
+            ui.checkbox(hash!(), "Time Dilation", &mut time_dilation);
+            if time_dilation {
+                let dilation = &mut state.time_dilation;
+                let mut reference_energy = dilation.reference_energy as f32;
+                let mut min_tick_rate = dilation.min_tick_rate as f32;
+                let mut max_tick_rate = dilation.max_tick_rate as f32;
+                ui.slider(
+                    hash!(),
+                    "Reference Energy",
+                    0.01..5.0,
+                    &mut reference_energy,
+                );
+                ui.slider(hash!(), "Min TPS", 1.0..60.0, &mut min_tick_rate);
+                ui.slider(hash!(), "Max TPS", 1.0..240.0, &mut max_tick_rate);
+                dilation.reference_energy = reference_energy as f64;
+                dilation.min_tick_rate = min_tick_rate as f64;
+                dilation.max_tick_rate = max_tick_rate as f64;
+                thread_data.time_dilation = Some(*dilation);
+
+                // The size of a step doesn't change, so the speed particles move at on screen
+                // only follows the tick rate that is actually reached
+                let (energy, _) = simulation.kinetic_energy_stats();
+                let target_speed = dilation.tick_rate(energy) / TICK_RATE;
+                if let Some(tick_time) = thread_data.tick_time {
+                    ui.label(
+                        None,
+                        &format!(
+                            "Speed: {:.0}% (aiming for {:.0}%)",
+                            TimeDilation::effective_speed(tick_time) * 100.0,
+                            target_speed * 100.0,
+                        ),
+                    );
+                }
+            } else {
+                thread_data.time_dilation = None;
+            }
+
+            ui.separator();
+            ui.label(None, "Attractions");
+            ui.checkbox(
+                hash!(),
+                "Logarithmic Sliders",
+                &mut state.logarithmic_sliders,
+            );
+            let logarithmic = state.logarithmic_sliders;
+            // Logarithmic sliders can reach much larger scales without losing the small ones
+            let scale_range = if logarithmic { 0.0..1000.0 } else { 0.0..10.0 };
+            let positive_scale = simulation.type_data().positive_scale() as f32;
+            let mut new_positive_scale = positive_scale;
+            attraction_slider(
+                ui,
+                hash!(),
+                "Positive Scale",
+                scale_range.clone(),
+                logarithmic,
+                &mut new_positive_scale,
+            );
+            if new_positive_scale != positive_scale {
+                let positive_scale = new_positive_scale as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation
+                        .type_data_mut()
+                        .set_positive_scale(positive_scale)
+                }));
+            }
+            let negative_scale = simulation.type_data().negative_scale() as f32;
+            let mut new_negative_scale = negative_scale;
+            attraction_slider(
+                ui,
+                hash!(),
+                "Negative Scale",
+                scale_range,
+                logarithmic,
+                &mut new_negative_scale,
+            );
+            if new_negative_scale != negative_scale {
+                let negative_scale = new_negative_scale as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation
+                        .type_data_mut()
+                        .set_negative_scale(negative_scale)
+                }));
+            }
+
+            let num_types = simulation.type_data().num_types() as u32;
+            let max_types = MAX_TYPES.max(num_types);
+            let mut new_num_types = num_types;
+            ui.drag(hash!(), "Types", (1, max_types), &mut new_num_types);
+            // Typed in values aren't limited by the drag's range
+            new_num_types = new_num_types.clamp(1, max_types);
+            let mut removed_types = RemovedTypes::ALL
+                .iter()
+                .position(|&removed| removed == state.removed_types)
+                .unwrap();
+            ui.combo_box(
+                hash!(),
+                "Removed Types",
+                &RemovedTypes::NAMES,
+                &mut removed_types,
+            );
+            state.removed_types = RemovedTypes::ALL[removed_types];
+            if new_num_types != num_types {
+                let removed = state.removed_types;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_num_types(new_num_types as usize, removed)
+                }));
+            }
+
+            // The number of each type next to its color, since the type editor only shows numbers
+            let type_colors = simulation.type_data().colors();
+            if state
+                .legend
+                .as_ref()
+                .is_none_or(|(colors, _)| colors != type_colors)
+            {
+                state.legend = Some((type_colors.to_vec(), legend_swatches(type_colors)));
+            }
+            let (_, swatches) = state.legend.as_ref().unwrap();
+            for (typ, swatch) in swatches.iter().enumerate() {
+                if typ % LEGEND_COLUMNS != 0 {
+                    ui.same_line(0.0);
+                }
+                ui.texture(swatch.clone(), 12.0, 12.0);
+                ui.same_line(0.0);
+                ui.label(None, &typ.to_string());
+            }
+
+            let max_type = simulation.type_data().num_types().saturating_sub(1) as u32;
+            ui.drag(hash!(), "Source", (0, max_type), &mut state.edit_source);
+            ui.drag(hash!(), "Target", (0, max_type), &mut state.edit_target);
+            let [source, target] =
+                [state.edit_source, state.edit_target].map(|typ| typ.min(max_type) as usize);
+            // Random attractions can be stronger than 1, so the sliders grow to fit them
+            let limit = simulation.type_data().random_range().max(1.0) as f32;
+            let attraction = simulation.type_data().get_base_attraction(source, target) as f32;
+            let mut new_attraction = attraction;
+            attraction_slider(
+                ui,
+                hash!(),
+                "Attraction",
+                -limit..limit,
+                logarithmic,
+                &mut new_attraction,
+            );
+            if new_attraction != attraction {
+                let attraction = new_attraction as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation
+                        .type_data_mut()
+                        .set_attraction(source, target, attraction);
+                }));
+            }
+
+            // Every attraction of the source type, for editing a whole row at once
+            ui.checkbox(hash!(), "Show Source Row", &mut state.show_source_row);
+            if state.show_source_row {
+                for target in 0..simulation.type_data().num_types() {
+                    let attraction =
+                        simulation.type_data().get_base_attraction(source, target) as f32;
+                    let mut new_attraction = attraction;
+                    attraction_slider(
+                        ui,
+                        hash!("source row", target),
+                        &format!("To {target}"),
+                        -limit..limit,
+                        logarithmic,
+                        &mut new_attraction,
+                    );
+                    if new_attraction != attraction {
+                        let attraction = new_attraction as f64;
+                        thread_data.edits.push(Box::new(move |simulation| {
+                            simulation
+                                .type_data_mut()
+                                .set_attraction(source, target, attraction);
+                        }));
+                    }
+                }
+            }
+
+            if ui.button(None, "Invert") {
+                thread_data
+                    .edits
+                    .push(Box::new(|simulation| simulation.type_data_mut().invert()));
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Antisymmetrize") {
+                thread_data.edits.push(Box::new(|simulation| {
+                    simulation.type_data_mut().antisymmetrize()
+                }));
+            }
+
+            ui.slider(
+                hash!(),
+                "Diagonal Factor",
+                0.0..2.0,
+                &mut state.diagonal_factor,
+            );
+            if ui.button(None, "Scale Self-Attraction") {
+                let factor = state.diagonal_factor as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.type_data_mut().scale_diagonal(factor)
+                }));
+            }
+
+            ui.slider(hash!(), "Smooth Amount", 0.0..1.0, &mut state.smooth_amount);
+            ui.checkbox(hash!(), "Wrap Smoothing", &mut state.smooth_wrapping);
+            if ui.button(None, "Smooth") {
+                let amount = state.smooth_amount as f64;
+                let wrapping = state.smooth_wrapping;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    if wrapping {
+                        simulation.type_data_mut().smooth_wrapping(amount)
+                    } else {
+                        simulation.type_data_mut().smooth(amount)
+                    }
+                }));
+            }
+
+            let random_range = simulation.type_data().random_range() as f32;
+            let mut new_random_range = random_range;
+            ui.slider(hash!(), "Random Range", 0.0..10.0, &mut new_random_range);
+            if new_random_range != random_range {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation
+                        .type_data_mut()
+                        .set_random_range(new_random_range as f64)
+                }));
+            }
+            if ui.button(None, "Randomize") {
+                thread_data.edits.push(Box::new(|simulation| {
+                    simulation.type_data_mut().randomize_attractions()
+                }));
+            }
+
+            ui.slider(hash!(), "Jitter Amount", 0.0..0.5, &mut state.jitter_amount);
+            if ui.button(None, "Jitter") {
+                let amount = state.jitter_amount as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.type_data_mut().jitter(amount)
+                }));
+            }
+
+            ui.slider(
+                hash!(),
+                "Gradient Falloff",
+                0.1..25.0,
+                &mut state.gradient_falloff,
+            );
+            if ui.button(None, "Gradient") {
+                let falloff = state.gradient_falloff as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    let type_data = simulation.type_data_mut();
+                    let gradient = ParticleTypeData::new_gradient(
+                        type_data.num_types(),
+                        type_data.positive_scale(),
+                        falloff,
+                    );
+                    type_data.set_base_attractions(gradient.base_attractions().clone());
+                }));
+            }
+
+            if ui.button(None, "Copy Preset") {
+                miniquad::window::clipboard_set(&simulation.type_data().to_preset_string());
+                state.preset_status = "Copied preset".to_string();
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Paste Preset") {
+                let preset = miniquad::window::clipboard_get()
+                    .and_then(|preset| ParticleTypeData::from_preset_string(&preset));
+                state.preset_status = match preset {
+                    None => "The clipboard doesn't contain a preset".to_string(),
+                    Some(preset) => match check_preset(simulation, &preset) {
+                        Err(error) => error,
+                        Ok(()) => {
+                            thread_data.edits.push(Box::new(move |simulation| {
+                                // Keep the current colors
+                                let type_data = simulation.type_data_mut();
+                                type_data.set_base_attractions(preset.base_attractions().clone());
+                                type_data.set_positive_scale(preset.positive_scale());
+                                type_data.set_negative_scale(preset.negative_scale());
+                            }));
+                            "Applied preset".to_string()
+                        }
+                    },
+                };
+            }
+            if ui.button(None, "Copy Rust Code") {
+                miniquad::window::clipboard_set(&simulation.type_data().to_rust_snippet());
+                state.preset_status = "Copied Rust code".to_string();
+            }
+            ui.label(None, &state.preset_status);
+
+            if ui.button(None, "Set Morph Target") {
+                state.morph_target = Some(simulation.type_data().base_attractions().clone());
+            }
+            ui.slider(hash!(), "Morph Steps", 1.0..1000.0, &mut state.morph_steps);
+            if let Some(morph) = &thread_data.morph {
+                ui.label(None, &format!("Morphing: {} steps left", morph.steps_left));
+                ui.same_line(0.0);
+                if ui.button(None, "Stop") {
+                    thread_data.morph = None;
+                }
+            } else if let Some(target) = &state.morph_target {
+                if ui.button(None, "Start Morph") {
+                    thread_data.morph = Some(AttractionMorph {
+                        target: target.clone(),
+                        steps_left: state.morph_steps as usize,
+                    });
+                }
+            }
+
+            ui.separator();
+            ui.label(None, "Favorites");
+            text_fields.push(text_field(ui, hash!(), "Name", &mut state.favorite_name));
+            let name = state.favorite_name.trim();
+            if ui.button(None, "Save Favorite") && !name.is_empty() {
+                state.favorites.entries.push(Favorite {
+                    name: name.to_string(),
+                    type_data: simulation.type_data().clone(),
+                });
+                state.favorite_thumbnails = None;
+                state.favorites_status = save_favorites(&state.favorites);
+            }
+
+            let thumbnails = state.favorite_thumbnails.get_or_insert_with(|| {
+                state
+                    .favorites
+                    .entries
+                    .iter()
+                    .map(|favorite| thumbnail(&favorite.type_data))
+                    .collect()
+            });
+            let mut loaded = None;
+            let mut renamed = None;
+            let mut deleted = None;
+            for (i, favorite) in state.favorites.entries.iter().enumerate() {
+                if ui.texture(thumbnails[i].clone(), 32.0, 32.0) {
+                    loaded = Some(i);
+                }
+                ui.same_line(0.0);
+                ui.label(None, &favorite.name);
+                ui.same_line(0.0);
+                if ui.button(None, "Rename") {
+                    renamed = Some(i);
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Delete") {
+                    deleted = Some(i);
+                }
+            }
+
+            if let Some(i) = loaded {
+                let favorite = &state.favorites.entries[i];
+                state.favorites_status = match check_preset(simulation, &favorite.type_data) {
+                    Err(error) => format!("Can't load {}: {error}", favorite.name),
+                    Ok(()) => {
+                        let type_data = favorite.type_data.clone();
+                        thread_data.edits.push(Box::new(move |simulation| {
+                            *simulation.type_data_mut() = type_data;
+                        }));
+                        format!("Loaded {}", favorite.name)
+                    }
+                };
+            }
+            if let Some(i) = renamed {
+                let name = state.favorite_name.trim();
+                if name.is_empty() {
+                    state.favorites_status = "Enter a name to rename to".to_string();
+                } else {
+                    state.favorites.entries[i].name = name.to_string();
+                    state.favorites_status = save_favorites(&state.favorites);
+                }
+            }
+            if let Some(i) = deleted {
+                state.favorites.entries.remove(i);
+                state.favorite_thumbnails = None;
+                state.favorites_status = save_favorites(&state.favorites);
+            }
+            ui.label(None, &state.favorites_status);
+
+            ui.separator();
+            ui.label(None, "Exploration");
+            if state
+                .last_score
+                .is_none_or(|(_, time)| time.elapsed() > SCORE_INTERVAL)
+            {
+                state.last_score = Some((analysis::interestingness(simulation), Instant::now()));
+            }
+            if let Some((score, _)) = state.last_score {
+                ui.label(None, &format!("Interestingness: {score:.3}"));
+            }
+            ui.slider(
+                hash!(),
+                "Steps per Trial",
+                10.0..1000.0,
+                &mut state.explore_steps,
+            );
+            ui.slider(hash!(), "Trials", 1.0..50.0, &mut state.explore_trials);
+            if state.exploration.is_some() {
+                ui.label(None, "Exploring...");
+            } else if ui.button(None, "Auto Explore") {
+                let simulation = simulation.clone();
+                let steps = state.explore_steps as usize;
+                let trials = state.explore_trials as usize;
+                let (sender, receiver) = mpsc::channel();
+                thread::spawn(move || {
+                    let _ = sender.send(analysis::explore(&simulation, steps, trials));
+                });
+                state.exploration = Some(receiver);
+            }
+            ui.label(None, &state.explore_status);
+
+            ui.separator();
+            ui.label(None, "Recording");
+            if let Some(recorder) = &thread_data.recorder {
+                ui.label(
+                    None,
+                    &format!(
+                        "Recording {} every {} steps: {} rows",
+                        Metric::NAMES[Metric::ALL
+                            .iter()
+                            .position(|&metric| metric == recorder.metric)
+                            .unwrap()],
+                        recorder.interval,
+                        recorder.rows()
+                    ),
+                );
+                if ui.button(None, "Stop Recording") {
+                    // Dropping the recorder flushes the rest of the rows
+                    thread_data.recorder = None;
+                    state.recording_status = format!("Saved to {RECORDING_PATH}");
+                }
+            } else {
+                ui.combo_box(
+                    hash!(),
+                    "Metric",
+                    &Metric::NAMES,
+                    &mut state.recording_metric,
+                );
+                ui.slider(
+                    hash!(),
+                    "Interval",
+                    1.0..1000.0,
+                    &mut state.recording_interval,
+                );
+                if ui.button(None, "Start Recording") {
+                    let recorder = MetricRecorder::new(
+                        Metric::ALL[state.recording_metric],
+                        state.recording_interval as usize,
+                        simulation.type_data().num_types(),
+                    );
+                    match recorder {
+                        Ok(recorder) => {
+                            thread_data.recorder = Some(recorder);
+                            state.recording_status.clear();
+                        }
+                        Err(error) => {
+                            state.recording_status = format!("Failed to start recording: {error}")
+                        }
+                    }
+                }
+            }
+            if let Some(error) = thread_data.recording_error.take() {
+                state.recording_status = format!("Recording stopped: {error}");
+            }
+            ui.label(None, &state.recording_status);
+
+            ui.separator();
+            ui.label(None, "Orbits");
+            let mut detect_orbits = thread_data.orbits.is_some();
+            ui.checkbox(hash!(), "Detect Orbits", &mut detect_orbits);
+            ui.slider(hash!(), "Window", 10.0..600.0, &mut state.orbit_window);
+            ui.slider(hash!(), "Max Drift", 0.0..1.0, &mut state.orbit_max_drift);
+            ui.slider(hash!(), "Min Path", 0.0..500.0, &mut state.orbit_min_path);
+            if detect_orbits != thread_data.orbits.is_some() {
+                thread_data.orbits = detect_orbits.then(OrbitDetector::default);
+            }
+            if let Some(detector) = &mut thread_data.orbits {
+                detector.window = state.orbit_window as usize;
+                detector.max_drift = state.orbit_max_drift as f64;
+                detector.min_path = state.orbit_min_path as f64;
+                ui.label(None, &format!("Orbiting: {}", detector.orbit_count()));
+                state.orbits.clone_from(detector.orbiting());
+            } else {
+                state.orbits.clear();
+            }
+
+            ui.separator();
+            ui.label(None, "Spawning");
+            let seed = simulation.seed();
+            ui.label(None, &format!("Seed: {seed}"));
+            ui.same_line(0.0);
+            if ui.button(None, "Copy") {
+                miniquad::window::clipboard_set(&seed.to_string());
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "New Seed") {
+                // Respawn the same number of particles from a new seed
+                let seed = rand::random();
+                thread_data.edits.push(Box::new(move |simulation| {
+                    let count = simulation.num_particles();
+                    simulation.clear_particles();
+                    simulation.set_seed(seed);
+                    simulation.add_random_particles(count);
+                }));
+            }
+            if ui.button(None, "Copy State Hash") {
+                let hash = simulation.state_hash();
+                miniquad::window::clipboard_set(&format!("{hash:016x}"));
+            }
+            ui.combo_box(
+                hash!(),
+                "Pattern",
+                &SpawnPattern::NAMES,
+                &mut state.spawn_pattern,
+            );
+
+            let pattern = SpawnPattern::ALL[state.spawn_pattern];
+            if matches!(
+                pattern,
+                SpawnPattern::Random
+                    | SpawnPattern::Circle
+                    | SpawnPattern::Ring
+                    | SpawnPattern::Poisson
+            ) {
+                ui.slider(hash!(), "Count", 0.0..10000.0, &mut state.spawn_count);
+            }
+            if matches!(pattern, SpawnPattern::Circle | SpawnPattern::Ring) {
+                let max_radius = world_size[0].max(world_size[1]) as f32 / 2.0;
+                ui.slider(hash!(), "Radius", 0.0..max_radius, &mut state.spawn_radius);
+            }
+            if pattern == SpawnPattern::Grid {
+                ui.slider(hash!(), "Spacing", 5.0..100.0, &mut state.spawn_spacing);
+            }
+            if pattern == SpawnPattern::Clustered {
+                ui.slider(hash!(), "Per Type", 0.0..200.0, &mut state.spawn_per_type);
+                ui.slider(hash!(), "Spread", 0.0..200.0, &mut state.spawn_spread);
+            }
+            if pattern == SpawnPattern::Poisson {
+                let max_distance = simulation.interaction_range() as f32;
+                ui.slider(
+                    hash!(),
+                    "Min Distance",
+                    1.0..max_distance,
+                    &mut state.spawn_min_distance,
+                );
+            }
+
+            let mut spawn: Option<(usize, SimulationEdit)> = None;
+            if ui.button(None, "Spawn") {
+                let count = state.spawn_count as usize;
+                let radius = state.spawn_radius as f64;
+                let spacing = state.spawn_spacing as f64;
+                let per_type = state.spawn_per_type as usize;
+                let spread = state.spawn_spread as f64;
+                let min_distance = state.spawn_min_distance as f64;
+                spawn = Some(match pattern {
+                    SpawnPattern::Random => (
+                        count,
+                        Box::new(move |simulation| simulation.add_random_particles(count)),
+                    ),
+                    SpawnPattern::Circle => (
+                        count,
+                        Box::new(move |simulation| {
+                            simulation.add_particles_in_circle(center, radius, count)
+                        }),
+                    ),
+                    SpawnPattern::Ring => (
+                        count,
+                        Box::new(move |simulation| {
+                            simulation.add_particles_in_ring(center, radius / 2.0, radius, count)
+                        }),
+                    ),
+                    SpawnPattern::Grid => (
+                        world_size
+                            .map(|x| (x / spacing).floor() as usize)
+                            .iter()
+                            .product(),
+                        Box::new(move |simulation| simulation.add_particles_in_grid(spacing)),
+                    ),
+                    SpawnPattern::Clustered => (
+                        per_type * simulation.type_data().num_types(),
+                        Box::new(move |simulation| {
+                            simulation.add_clustered_particles(per_type, spread)
+                        }),
+                    ),
+                    SpawnPattern::Poisson => (
+                        count,
+                        Box::new(move |simulation| {
+                            simulation.add_particles_poisson(count, min_distance);
+                        }),
+                    ),
+                });
+            }
+
+            let spawn_speed = simulation.spawn_speed() as f32;
+            let mut new_spawn_speed = spawn_speed;
+            ui.slider(hash!(), "Spawn Speed", 0.0..20.0, &mut new_spawn_speed);
+            if new_spawn_speed != spawn_speed {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_spawn_speed(new_spawn_speed as f64)
+                }));
+            }
+
+            let max_particles = simulation.max_particles() as u32;
+            let mut new_max_particles = max_particles;
+            ui.drag(
+                hash!(),
+                "Max Particles",
+                (0, 10_000_000),
+                &mut new_max_particles,
+            );
+            if new_max_particles != max_particles {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_max_particles(new_max_particles as usize)
+                }));
+            }
+            if simulation.hit_particle_limit() {
+                ui.label(
+                    None,
+                    &format!("Stopped spawning at the limit of {max_particles} particles"),
+                );
+            }
+
+            let max_type = simulation.type_data().num_types().saturating_sub(1) as u32;
+            ui.drag(hash!(), "Cap Type", (0, max_type), &mut state.cap_type);
+            ui.drag(hash!(), "Cap", (0, 100000), &mut state.cap_count);
+            let typ = state.cap_type as usize;
+            ui.label(
+                None,
+                &match simulation.type_cap(typ) {
+                    Some(cap) => format!("Spawning stops at {cap} of type {typ}"),
+                    None => format!("Type {typ} has no cap"),
+                },
+            );
+            if ui.button(None, "Set Cap") {
+                let cap = state.cap_count as usize;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_type_cap(typ, Some(cap))
+                }));
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Remove Cap") {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_type_cap(typ, None)
+                }));
+            }
+
+            ui.separator();
+            ui.slider(hash!(), "Density", 0.0..0.01, &mut state.fill_density);
+            if ui.button(None, "Fill to Density") {
+                spawn = Some(fill_to_density(state, simulation));
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Clear") {
+                thread_data
+                    .edits
+                    .push(Box::new(|simulation| simulation.clear_particles()));
+            }
+
+            if let Some((count, edit)) = spawn {
+                request_spawn(state, thread_data, count, edit);
+            }
+
+            if let Some((count, _)) = state.pending_spawn {
+                ui.label(None, &format!("Spawn {count} particles?"));
+                if ui.button(None, "Confirm") {
+                    let (_, edit) = state.pending_spawn.take().unwrap();
+                    thread_data.edits.push(edit);
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Cancel") {
+                    state.pending_spawn = None;
+                }
+            }
+
+            ui.separator();
+            ui.label(None, "Selection");
+            if state.selection.is_empty() {
+                ui.label(None, "Shift+Drag to select particles");
+            } else {
+                let selected = simulation
+                    .particles()
+                    .filter(|particle| state.selection.contains(&particle.id()))
+                    .count();
+                ui.label(None, &format!("Selected: {selected}"));
+                if ui.button(None, "Delete Selected") {
+                    let selection = std::mem::take(&mut state.selection);
+                    thread_data.edits.push(Box::new(move |simulation| {
+                        simulation.edit_particles(|particle| !selection.contains(&particle.id()))
+                    }));
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Clear Selection") {
+                    state.selection.clear();
+                }
+
+                let max_type = simulation.type_data().num_types().saturating_sub(1) as u32;
+                ui.drag(hash!(), "Type", (0, max_type), &mut state.selection_type);
+                if ui.button(None, "Retype Selected") {
+                    let selection = state.selection.clone();
+                    let typ = state.selection_type.min(max_type) as usize;
+                    thread_data.edits.push(Box::new(move |simulation| {
+                        simulation.edit_particles(|particle| {
+                            if selection.contains(&particle.id()) {
+                                particle.typ = typ;
+                            }
+                            true
+                        })
+                    }));
+                }
+                let reset_velocity = simulation.reset_velocity_on_retype();
+                let mut new_reset_velocity = reset_velocity;
+                ui.checkbox(hash!(), "Reset Velocity On Retype", &mut new_reset_velocity);
+                if new_reset_velocity != reset_velocity {
+                    thread_data.edits.push(Box::new(move |simulation| {
+                        simulation.set_reset_velocity_on_retype(new_reset_velocity)
+                    }));
+                }
+
+                ui.slider(
+                    hash!(),
+                    "Nudge Distance",
+                    1.0..100.0,
+                    &mut state.nudge_distance,
+                );
+                let distance = state.nudge_distance as f64;
+                for (i, (label, offset)) in [
+                    ("Left", [-distance, 0.0]),
+                    ("Right", [distance, 0.0]),
+                    ("Up", [0.0, -distance]),
+                    ("Down", [0.0, distance]),
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    if i > 0 {
+                        ui.same_line(0.0);
+                    }
+                    if ui.button(None, label) {
+                        let selection = state.selection.clone();
+                        thread_data.edits.push(Box::new(move |simulation| {
+                            simulation.edit_particles(|particle| {
+                                if selection.contains(&particle.id()) {
+                                    particle.position[0] += offset[0];
+                                    particle.position[1] += offset[1];
+                                }
+                                true
+                            })
+                        }));
+                    }
+                }
+            }
+
+            ui.separator();
+            ui.label(None, "World");
+            ui.drag(hash!(), "Width", (1, 500), &mut state.grid_width);
+            ui.drag(hash!(), "Height", (1, 500), &mut state.grid_height);
+            let grid_size = [state.grid_width as usize, state.grid_height as usize];
+            let bucket_size = simulation.bucket_size();
+            ui.label(
+                None,
+                &format!(
+                    "Size: {} x {} (currently {} x {})",
+                    grid_size[0] as f64 * bucket_size,
+                    grid_size[1] as f64 * bucket_size,
+                    world_size[0],
+                    world_size[1],
+                ),
+            );
+            if ui.button(None, "Resize") {
+                thread_data.grid_size = grid_size;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.resize_grid(grid_size)
+                }));
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Reset With Size") {
+                thread_data.grid_size = grid_size;
+                thread_data.reset = true;
+            }
+
+            ui.combo_box(hash!(), "Scenario", &Scenario::NAMES, &mut state.scenario);
+            if ui.button(None, "Load Scenario") {
+                let scenario = Scenario::ALL[state.scenario];
+                thread_data.edits.push(Box::new(move |simulation| {
+                    *simulation = scenario.build();
+                }));
+            }
+
+            let repulsion_distance = simulation.params.repulsion_distance as f32;
+            let mut new_repulsion_distance = repulsion_distance;
+            ui.slider(
+                hash!(),
+                "Repulsion Distance",
+                0.0..50.0,
+                &mut new_repulsion_distance,
+            );
+            if new_repulsion_distance != repulsion_distance {
+                let repulsion_distance = new_repulsion_distance as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.params.repulsion_distance = repulsion_distance
+                }));
+            }
+
+            let close_range_repulsion = simulation.params.close_range_repulsion;
+            let mut new_close_range_repulsion = close_range_repulsion;
+            ui.checkbox(
+                hash!(),
+                "Close Range Repulsion",
+                &mut new_close_range_repulsion,
+            );
+            if new_close_range_repulsion != close_range_repulsion {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.params.close_range_repulsion = new_close_range_repulsion
+                }));
+            }
+
+            let prevent_ejecting = simulation.params.prevent_particle_ejecting;
+            let mut new_prevent_ejecting = prevent_ejecting;
+            ui.checkbox(hash!(), "Prevent Ejecting", &mut new_prevent_ejecting);
+            if new_prevent_ejecting != prevent_ejecting {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.params.prevent_particle_ejecting = new_prevent_ejecting
+                }));
+            }
+            if prevent_ejecting {
+                let ejection_distance = simulation.params.ejection_distance as f32;
+                let mut new_ejection_distance = ejection_distance;
+                ui.slider(
+                    hash!(),
+                    "Ejection Distance",
+                    0.0..PARTICLE_RADIUS as f32,
+                    &mut new_ejection_distance,
+                );
+                if new_ejection_distance != ejection_distance {
+                    thread_data.edits.push(Box::new(move |simulation| {
+                        simulation.params.ejection_distance = new_ejection_distance as f64
+                    }));
+                }
+                let overlap_strength = simulation.params.overlap_strength as f32;
+                let mut new_overlap_strength = overlap_strength;
+                ui.slider(
+                    hash!(),
+                    "Overlap Strength",
+                    0.0..50.0,
+                    &mut new_overlap_strength,
+                );
+                if new_overlap_strength != overlap_strength {
+                    thread_data.edits.push(Box::new(move |simulation| {
+                        simulation.params.overlap_strength = new_overlap_strength as f64
+                    }));
+                }
+            }
+
+            let softening = simulation.params.softening as f32;
+            let mut new_softening = softening;
+            ui.slider(hash!(), "Softening", 0.0..10.0, &mut new_softening);
+            if new_softening != softening {
+                let softening = new_softening as f64;
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.params.softening = softening
+                }));
+            }
+
+            let coincident_particles = CoincidentParticles::ALL
+                .iter()
+                .position(|&strategy| strategy == simulation.params.coincident_particles)
+                .unwrap();
+            let mut new_coincident_particles = coincident_particles;
+            ui.combo_box(
+                hash!(),
+                "Same Position",
+                &CoincidentParticles::NAMES,
+                &mut new_coincident_particles,
+            );
+            if new_coincident_particles != coincident_particles {
+                let strategy = CoincidentParticles::ALL[new_coincident_particles];
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.params.coincident_particles = strategy
+                }));
+            }
+
+            let neighbor_search = NeighborSearch::ALL
+                .iter()
+                .position(|&strategy| strategy == simulation.params.neighbor_search)
+                .unwrap();
+            let mut new_neighbor_search = neighbor_search;
+            ui.combo_box(
+                hash!(),
+                "Neighbor Search",
+                &NeighborSearch::NAMES,
+                &mut new_neighbor_search,
+            );
+            if new_neighbor_search != neighbor_search {
+                let strategy = NeighborSearch::ALL[new_neighbor_search];
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.params.neighbor_search = strategy
+                }));
+            }
+
+            let auto_subdivision = simulation.auto_subdivision();
+            let mut auto = auto_subdivision.is_some();
+            ui.checkbox(hash!(), "Auto Subdivide", &mut auto);
+            let new_auto_subdivision = if auto {
+                let mut max_occupancy = auto_subdivision.unwrap_or(50.0) as f32;
+                ui.slider(hash!(), "Max Per Bucket", 1.0..200.0, &mut max_occupancy);
+                Some(max_occupancy.round() as f64)
+            } else {
+                let subdivision = simulation.subdivision() as f32;
+                let mut new_subdivision = subdivision;
+                ui.slider(hash!(), "Subdivision", 1.0..4.0, &mut new_subdivision);
+                // Whole factors keep the size of the simulation the same
+                let new_subdivision = new_subdivision.round();
+                if new_subdivision != subdivision {
+                    thread_data.edits.push(Box::new(move |simulation| {
+                        simulation.set_subdivision(new_subdivision as f64)
+                    }));
+                }
+                None
+            };
+            if new_auto_subdivision != auto_subdivision {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.set_auto_subdivision(new_auto_subdivision)
+                }));
+            }
+
+            let soft_boundary = simulation.params.soft_boundary;
+            let mut enabled = soft_boundary.is_some();
+            let mut boundary = soft_boundary.unwrap_or_default();
+            ui.checkbox(hash!(), "Soft Boundary", &mut enabled);
+            if enabled {
+                let mut margin = boundary.margin as f32;
+                let mut strength = boundary.strength as f32;
+                ui.slider(hash!(), "Margin", 0.0..500.0, &mut margin);
+                ui.slider(hash!(), "Strength", 0.0..2.0, &mut strength);
+                boundary.margin = margin as f64;
+                boundary.strength = strength as f64;
+            }
+            let new_soft_boundary = enabled.then_some(boundary);
+            if new_soft_boundary != soft_boundary {
+                thread_data.edits.push(Box::new(move |simulation| {
+                    simulation.params.soft_boundary = new_soft_boundary
+                }));
+            }
+
+            if state.confirm_reset_all {
+                ui.label(None, "Reset the simulation and all controls?");
+                ui.label(None, "This stops stepping, morphing, time dilation,");
+                ui.label(None, "recording and orbit detection. Favorites and");
+                ui.label(None, "other settings are kept.");
+                if ui.button(None, "Confirm") {
+                    reset_all(state, settings, thread_data);
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Cancel") {
+                    state.confirm_reset_all = false;
+                }
+            } else if ui.button(None, "Reset All") {
+                state.confirm_reset_all = true;
+            }
+
+            ui.separator();
+            ui.label(None, "Settings");
+            ui.slider(
+                hash!(),
+                "Confirm Above",
+                0.0..100000.0,
+                &mut state.confirmation_threshold,
+            );
+
+            ui.slider(hash!(), "Idle FPS", 0.0..60.0, &mut settings.idle_fps);
+            ui.slider(hash!(), "FPS Limit", 0.0..240.0, &mut settings.fps_limit);
+            ui.label(
+                None,
+                &if settings.fps_limit > 0.0 {
+                    format!("Rendering at up to {:.0} FPS", settings.fps_limit)
+                } else {
+                    "Rendering at unlimited FPS".to_string()
+                },
+            );
+            ui.slider(hash!(), "TPS Limit", 0.0..240.0, &mut settings.tps_limit);
+            text_fields.push(text_field(ui, hash!(), "Custom TPS", &mut state.custom_tps));
+            ui.same_line(0.0);
+            if ui.button(None, "Set TPS") {
+                match settings::parse_rate(&state.custom_tps) {
+                    Some(rate) => settings.tps_limit = rate,
+                    None => {
+                        state.settings_status =
+                            format!("\"{}\" isn't a tick rate", state.custom_tps.trim())
+                    }
+                }
+            }
+            ui.label(
+                None,
+                &if settings.tps_limit > 0.0 {
+                    format!("Simulating at up to {} TPS", settings.tps_limit)
+                } else {
+                    "Simulating at unlimited TPS".to_string()
+                },
+            );
+            ui.checkbox(hash!(), "Keep Camera In World", &mut settings.camera_bounds);
+            for (i, preset) in settings.zoom_presets.iter_mut().enumerate() {
+                ui.slider(
+                    hash!("zoom preset", i),
+                    &format!("Zoom Preset {}", i + 1),
+                    0.5..20.0,
+                    preset,
+                );
+            }
+            ui.checkbox(hash!(), "Scale Bar", &mut settings.show_scale_bar);
+            ui.checkbox(hash!(), "Refit On Resize", &mut settings.refit_on_resize);
+            color_sliders(ui, hash!(), "Background", &mut settings.background_color);
+
+            let draw_settings = &mut settings.draw_settings;
+            ui.checkbox(hash!(), "Draw Border", &mut draw_settings.draw_border);
+            if draw_settings.draw_border {
+                color_sliders(ui, hash!(), "Border", &mut draw_settings.border_color);
+                ui.slider(
+                    hash!(),
+                    "Border Thickness",
+                    0.1..5.0,
+                    &mut draw_settings.border_thickness,
+                );
+                let mut style = BorderStyle::ALL
+                    .iter()
+                    .position(|&style| style == draw_settings.border_style)
+                    .unwrap();
+                ui.combo_box(hash!(), "Border Style", &BorderStyle::NAMES, &mut style);
+                draw_settings.border_style = BorderStyle::ALL[style];
+                ui.checkbox(
+                    hash!(),
+                    "Corner Markers",
+                    &mut draw_settings.border_corner_markers,
+                );
+            }
+            ui.checkbox(
+                hash!(),
+                "Draw Bucket Edges",
+                &mut draw_settings.draw_bucket_edges,
+            );
+            ui.checkbox(
+                hash!(),
+                "Background Grid",
+                &mut draw_settings.draw_background_grid,
+            );
+            if draw_settings.draw_background_grid {
+                ui.drag(
+                    hash!(),
+                    "Grid Divisions",
+                    (1, 16),
+                    &mut draw_settings.background_grid_divisions,
+                );
+                let grid_color = &mut draw_settings.background_grid_color;
+                color_sliders(ui, hash!(), "Grid", grid_color);
+                ui.slider(hash!(), "Grid Opacity", 0.0..1.0, &mut grid_color.a);
+            }
+            ui.checkbox(
+                hash!(),
+                "Debug Interaction Range",
+                &mut draw_settings.draw_interaction_range,
+            );
+            ui.slider(
+                hash!(),
+                "Line Thickness",
+                0.1..5.0,
+                &mut draw_settings.line_thickness,
+            );
+            ui.checkbox(
+                hash!(),
+                "Velocity Vectors",
+                &mut draw_settings.draw_velocities,
+            );
+            if draw_settings.draw_velocities {
+                ui.slider(
+                    hash!(),
+                    "Velocity Scale",
+                    0.5..50.0,
+                    &mut draw_settings.velocity_scale,
+                );
+            }
+            ui.checkbox(hash!(), "Impulse Vectors", &mut draw_settings.draw_impulses);
+            if draw_settings.draw_impulses {
+                ui.slider(
+                    hash!(),
+                    "Impulse Scale",
+                    1.0..200.0,
+                    &mut draw_settings.impulse_scale,
+                );
+            }
+
+            let mut shape = ParticleShape::ALL
+                .iter()
+                .position(|&shape| shape == draw_settings.particle_shape)
+                .unwrap();
+            ui.combo_box(hash!(), "Particle Shape", &ParticleShape::NAMES, &mut shape);
+            draw_settings.particle_shape = ParticleShape::ALL[shape];
+            ui.checkbox(
+                hash!(),
+                "Sort By Type",
+                &mut draw_settings.sort_particles_by_type,
+            );
+
+            ui.checkbox(hash!(), "Bloom", &mut settings.bloom.enabled);
+            if settings.bloom.enabled {
+                ui.slider(
+                    hash!(),
+                    "Bloom Intensity",
+                    0.0..4.0,
+                    &mut settings.bloom.intensity,
+                );
+                ui.slider(
+                    hash!(),
+                    "Bloom Threshold",
+                    0.0..1.0,
+                    &mut settings.bloom.threshold,
+                );
+            }
+
+            if ui.button(None, "Save Settings") {
+                state.settings_status = match settings.save() {
+                    Ok(()) => "Saved".to_string(),
+                    Err(error) => format!("Failed to save: {error}"),
+                };
+            }
+            ui.same_line(0.0);
+            ui.label(None, &state.settings_status);
+
+            if ui.button(None, "Save Session") {
+                state.session_action = Some(SessionAction::Save);
+            }
+            ui.same_line(0.0);
+            if ui.button(None, "Load Session") {
+                state.session_action = Some(SessionAction::Load);
+            }
+            ui.label(None, &state.session_status);
+
+            ui.separator();
+            ui.label(None, "Controls");
+            for line in CONTROLS {
+                ui.label(None, line);
+            }
+        });
+
+    // Like in megaui, clicking a text field gives it keyboard focus and clicking anywhere else
+    // takes it away again
+    if input::is_mouse_button_pressed(MouseButton::Left) {
+        let mouse_position = Vec2::from(input::mouse_position());
+        state.text_focus = text_fields
+            .iter()
+            .any(|field: &Rect| field.contains(mouse_position));
+    }
+}
+
+/// Draws a text field like `Ui::input_text`, but at a fixed size so that the area that can be
+/// clicked to focus it is known, and returns that area.
+fn text_field(ui: &mut Ui, id: Id, label: &str, text: &mut String) -> Rect {
+    let position = ui.canvas().request_space(TEXT_FIELD_SIZE);
+    InputText::new(id)
+        .label(label)
+        .position(position)
+        .size(TEXT_FIELD_SIZE)
+        .ui(ui, text);
+    // The label takes up the right half, and isn't part of the field itself
+    Rect::new(
+        position.x,
+        position.y,
+        TEXT_FIELD_SIZE.x * TEXT_FIELD_RATIO - 15.0,
+        TEXT_FIELD_SIZE.y,
+    )
+}
+
+/// Starts a new simulation with the default grid size and puts the controls and the TPS limit
+/// back to their defaults. Everything the simulation thread does on its own is stopped: pending
+/// edits and steps, morphing, time dilation, recording (keeping the rows recorded so far) and
+/// orbit detection. The favorites and every other setting are kept.
+pub fn reset_all(
+    state: &mut UiState,
+    settings: &mut AppSettings,
+    thread_data: &mut SimulationThreadData,
+) {
+    thread_data.grid_size = DEFAULT_GRID_SIZE;
+    thread_data.reset = true;
+    thread_data.edits.clear();
+    thread_data.steps = 0;
+    thread_data.morph = None;
+    thread_data.time_dilation = None;
+    // Dropping the recorder flushes the rest of the rows
+    thread_data.recorder = None;
+    thread_data.recording_error = None;
+    thread_data.orbits = None;
+    settings.tps_limit = AppSettings::default().tps_limit;
+    state.reset();
+}
+
+/// Checks that a preset that was loaded from outside of the app can replace the attractions of
+/// `simulation`, returning the reason it can't otherwise.
+pub fn check_preset(
+    simulation: &ParticleSimulation,
+    preset: &ParticleTypeData,
+) -> Result<(), String> {
+    let num_types = simulation.type_data().num_types();
+    if preset.num_types() != num_types {
+        return Err(format!(
+            "The preset has {} types instead of {num_types}",
+            preset.num_types()
+        ));
+    }
+    simulation
+        .validate_type_data(preset)
+        .map_err(|error| error.to_string())
+}
+
+fn save_favorites(favorites: &Favorites) -> String {
+    match favorites.save() {
+        Ok(()) => "Saved favorites".to_string(),
+        Err(error) => format!("Failed to save favorites: {error}"),
+    }
+}
+
+/// One pixel for each attraction, with the source type along the x axis. Attractions are green
+/// and repulsions are red.
+fn thumbnail(type_data: &ParticleTypeData) -> Texture2D {
+    let num_types = type_data.num_types();
+    let mut bytes = Vec::with_capacity(num_types.pow(2) * 4);
+    for target in 0..num_types {
+        for source in 0..num_types {
+            let attraction = type_data
+                .get_base_attraction(source, target)
+                .clamp(-1.0, 1.0);
+            let red = (-attraction).max(0.0) * 255.0;
+            let green = attraction.max(0.0) * 255.0;
+            bytes.extend_from_slice(&[red as u8, green as u8, 0, 255]);
+        }
+    }
+    if bytes.is_empty() {
+        bytes = vec![0, 0, 0, 255];
+    }
+    let size = num_types.max(1) as u16;
+    let texture = Texture2D::from_rgba8(size, size, &bytes);
+    texture.set_filter(FilterMode::Nearest);
+    texture
+}
+
+/// The number of swatches in each row of the legend.
+const LEGEND_COLUMNS: usize = 10;
+
+fn legend_swatches(colors: &[Color]) -> Vec<Texture2D> {
+    colors
+        .iter()
+        .map(|&color| {
+            let bytes: [u8; 4] = color.into();
+            let texture = Texture2D::from_rgba8(1, 1, &bytes);
+            texture.set_filter(FilterMode::Nearest);
+            texture
+        })
+        .collect()
+}
+
+fn color_sliders(ui: &mut Ui, id: Id, label: &str, color: &mut Color) {
+    for (component, value) in
+        ["R", "G", "B"]
+            .into_iter()
+            .zip([&mut color.r, &mut color.g, &mut color.b])
+    {
+        ui.slider(
+            hash!(id, component),
+            &format!("{label} {component}"),
+            0.0..1.0,
+            value,
+        );
+    }
+}
+
+/// A slider for a scale or attraction. When `logarithmic` is set, the slider moves along
+/// `log10(|value| / min + 1)` in stead, with the sign of the value, where `min` is a thousandth of
+/// the largest value in `range`. This spreads the values from `min` to the largest value evenly
+/// along the slider, while still passing through 0. The value is shown after the label, since
+/// the slider's own number is the logarithm.
+fn attraction_slider(
+    ui: &mut Ui,
+    id: Id,
+    label: &str,
+    range: Range<f32>,
+    logarithmic: bool,
+    value: &mut f32,
+) {
+    if !logarithmic {
+        ui.slider(id, label, range, value);
+        return;
+    }
+
+    let min = range.start.abs().max(range.end.abs()) / 1000.0;
+    let to_slider = |value: f32| value.signum() * (value.abs() / min + 1.0).log10();
+    let position = to_slider(*value);
+    let mut new_position = position;
+    ui.slider(
+        id,
+        &format!("{label} ({value:.3})"),
+        to_slider(range.start)..to_slider(range.end),
+        &mut new_position,
+    );
+    // Only convert back when the slider moved, so an untouched value isn't changed by rounding
+    if new_position != position {
+        let new_value = new_position.signum() * min * (10f32.powf(new_position.abs()) - 1.0);
+        *value = new_value.clamp(range.start, range.end);
+    }
+}
+
+const CONTROLS: &[&str] = &[
+    "WASD: Pan, Scroll: Zoom",
+    "Ctrl+Click: Spawn circle, Ctrl+Scroll: Resize it",
+    "Ctrl+1-9: Brush type, Ctrl+0: Random, Ctrl+[ ]: Scroll",
+    "C: Center camera, 1-2: Zoom presets, M: Follow center of mass",
+    "Shift+Drag: Select particles",
+    "Space: Pause, R: Reset",
+    "F: Fill to density, Delete: Clear particles",
+    "F1: Info window, F2: Save view, F3: Debug, F11: Fullscreen",
+    "F4: Flow field (- and = to scale), F5: Bucket overlay",
+];
+
+/// Handles the hotkeys that mirror buttons in the info window. These are ignored while the mouse
+/// is over the ui.
+pub fn update_hotkeys(
+    state: &mut UiState,
+    simulation: &ParticleSimulation,
+    thread_data: &mut SimulationThreadData,
+) {
+    if state.text_focus || root_ui().is_mouse_over(input::mouse_position().into()) {
+        return;
+    }
+
+    if input::is_key_pressed(KeyCode::Delete) {
+        thread_data
+            .edits
+            .push(Box::new(|simulation| simulation.clear_particles()));
+    }
+
+    if input::is_key_pressed(KeyCode::F) {
+        let (count, edit) = fill_to_density(state, simulation);
+        request_spawn(state, thread_data, count, edit);
+    }
+}
+
+/// Whether the control key that turns the cursor into a spawning brush is held.
+pub fn brush_active() -> bool {
+    input::is_key_down(KeyCode::LeftControl) || input::is_key_down(KeyCode::RightControl)
+}
+
+/// The number of types that can be picked with the number keys at once.
+const HOTBAR_SIZE: usize = 9;
+
+const HOTBAR_KEYS: [KeyCode; HOTBAR_SIZE] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// While control is held, scrolling resizes the brush and clicking spawns a circle of particles
+/// under the cursor, using the count and radius from the spawning section. The number keys pick
+/// the type of particle to spawn from the hotbar, 0 goes back to random types, and the bracket
+/// keys scroll the hotbar.
+pub fn update_brush(
+    state: &mut UiState,
+    camera: &Camera2D,
+    simulation: &ParticleSimulation,
+    thread_data: &mut SimulationThreadData,
+) {
+    if !brush_active() || root_ui().is_mouse_over(input::mouse_position().into()) {
+        return;
+    }
+
+    let num_types = simulation.type_data().num_types();
+    let key_pressed = |key| !state.text_focus && input::is_key_pressed(key);
+    if key_pressed(KeyCode::RightBracket) && state.hotbar_offset + HOTBAR_SIZE < num_types {
+        state.hotbar_offset += HOTBAR_SIZE;
+    }
+    if key_pressed(KeyCode::LeftBracket) {
+        state.hotbar_offset = state.hotbar_offset.saturating_sub(HOTBAR_SIZE);
+    }
+    for (i, key) in HOTBAR_KEYS.into_iter().enumerate() {
+        let typ = state.hotbar_offset + i;
+        if key_pressed(key) && typ < num_types {
+            state.brush_type = Some(typ);
+        }
+    }
+    if key_pressed(KeyCode::Key0) {
+        state.brush_type = None;
+    }
+
+    let scroll = input::mouse_wheel().1;
+    if scroll != 0.0 {
+        state.spawn_radius = (state.spawn_radius * (1.0 + scroll * 0.1)).max(1.0);
+    }
+
+    if input::is_mouse_button_pressed(MouseButton::Left) {
+        let center = view::cursor_position(camera);
+        let center = [center.x as f64, center.y as f64];
+        let radius = state.spawn_radius as f64;
+        let count = state.spawn_count as usize;
+        let typ = state.brush_type;
+        request_spawn(
+            state,
+            thread_data,
+            count,
+            Box::new(move |simulation| match typ {
+                Some(typ) => simulation.add_typed_particles_in_circle(center, radius, count, typ),
+                None => simulation.add_particles_in_circle(center, radius, count),
+            }),
+        );
+    }
+}
+
+/// Whether dragging with the left mouse button draws a selection box.
+pub fn selecting() -> bool {
+    input::is_key_down(KeyCode::LeftShift) || input::is_key_down(KeyCode::RightShift)
+}
+
+/// Starts a selection box when shift dragging begins, and selects the particles inside it when
+/// the mouse button is released. Clicking without dragging clears the selection.
+pub fn update_selection(state: &mut UiState, camera: &Camera2D, simulation: &ParticleSimulation) {
+    let cursor = view::cursor_position(camera);
+    if input::is_mouse_button_pressed(MouseButton::Left)
+        && selecting()
+        && !brush_active()
+        && !root_ui().is_mouse_over(input::mouse_position().into())
+    {
+        state.selection_start = Some(cursor);
+    }
+
+    if let Some(start) = state.selection_start {
+        if !input::is_mouse_button_down(MouseButton::Left) {
+            state.selection_start = None;
+            let (min, max) = (start.min(cursor), start.max(cursor));
+            state.selection = simulation
+                .particles()
+                .filter(|particle| {
+                    let position = vec2(particle.position[0] as f32, particle.position[1] as f32);
+                    position.cmpge(min).all() && position.cmple(max).all()
+                })
+                .map(|particle| particle.id())
+                .collect();
+        }
+    }
+}
+
+/// Outlines the particles found by the orbit detector.
+pub fn draw_orbits(
+    state: &UiState,
+    camera: &Camera2D,
+    simulation: &ParticleSimulation,
+    line_thickness: f32,
+) {
+    if state.orbits.is_empty() {
+        return;
+    }
+    let thickness = line_thickness / view::pixels_per_unit(camera);
+    for particle in simulation.particles() {
+        if state.orbits.contains(&particle.id()) {
+            shapes::draw_circle_lines(
+                particle.position[0] as f32,
+                particle.position[1] as f32,
+                PARTICLE_RADIUS as f32 * 1.6,
+                thickness,
+                colors::YELLOW,
+            );
+        }
+    }
+}
+
+/// Outlines the selected particles and the selection box while it is dragged. The camera should
+/// be set to `camera`.
+pub fn draw_selection(
+    state: &UiState,
+    camera: &Camera2D,
+    simulation: &ParticleSimulation,
+    line_thickness: f32,
+) {
+    // Keep the outlines the same width on screen at any zoom
+    let thickness = line_thickness / view::pixels_per_unit(camera);
+    if !state.selection.is_empty() {
+        for particle in simulation.particles() {
+            if state.selection.contains(&particle.id()) {
+                shapes::draw_circle_lines(
+                    particle.position[0] as f32,
+                    particle.position[1] as f32,
+                    PARTICLE_RADIUS as f32 * 1.6,
+                    thickness,
+                    colors::WHITE,
+                );
+            }
+        }
+    }
+
+    if let Some(start) = state.selection_start {
+        let cursor = view::cursor_position(camera);
+        let (min, max) = (start.min(cursor), start.max(cursor));
+        let size = max - min;
+        shapes::draw_rectangle_lines(min.x, min.y, size.x, size.y, thickness, colors::WHITE);
+    }
+}
+
+/// Draws the outline of the brush at the cursor and the hotbar along the bottom of the screen.
+/// The camera should be set to `camera`, and this leaves the default camera set.
+pub fn draw_brush(
+    state: &UiState,
+    camera: &Camera2D,
+    simulation: &ParticleSimulation,
+    line_thickness: f32,
+) {
+    if !brush_active() {
+        return;
+    }
+
+    let center = view::cursor_position(camera);
+    // Keep the outline the same width on screen at any zoom
+    let thickness = line_thickness / view::pixels_per_unit(camera);
+    shapes::draw_circle_lines(
+        center.x,
+        center.y,
+        state.spawn_radius,
+        thickness,
+        colors::WHITE,
+    );
+
+    // Hotbar
+    camera::set_default_camera();
+    let type_colors = simulation.type_data().colors();
+    let slot_size = 40.0;
+    let left = (window::screen_width() - slot_size * HOTBAR_SIZE as f32) / 2.0;
+    let top = window::screen_height() - slot_size - 10.0;
+    for i in 0..HOTBAR_SIZE {
+        let typ = state.hotbar_offset + i;
+        let Some(&color) = type_colors.get(typ) else {
+            break;
+        };
+        let x = left + i as f32 * slot_size;
+        shapes::draw_rectangle(x + 4.0, top + 4.0, slot_size - 8.0, slot_size - 8.0, color);
+        if state.brush_type == Some(typ) {
+            shapes::draw_rectangle_lines(x, top, slot_size, slot_size, 4.0, colors::WHITE);
+        }
+        text::draw_text(
+            &(i + 1).to_string(),
+            x + 6.0,
+            top + 18.0,
+            20.0,
+            colors::BLACK,
+        );
+    }
+
+    let mut label = match state.brush_type {
+        Some(typ) => format!("Brush: type {typ}"),
+        None => "Brush: random types".to_string(),
+    };
+    let mut color = colors::WHITE;
+    if simulation.hit_particle_limit() {
+        label += " (particle limit reached)";
+        color = colors::RED;
+    }
+    text::draw_text(&label, left, top - 10.0, 32.0, color);
+}
+
+fn fill_to_density(state: &UiState, simulation: &ParticleSimulation) -> (usize, SimulationEdit) {
+    let density = state.fill_density as f64;
+    let count = simulation
+        .count_for_density(density)
+        .saturating_sub(simulation.num_particles());
+    (
+        count,
+        Box::new(move |simulation| simulation.fill_to_density(density)),
+    )
+}
+
+/// Queues a spawn, or holds on to it until it is confirmed if it would add too many particles.
+fn request_spawn(
+    state: &mut UiState,
+    thread_data: &mut SimulationThreadData,
+    count: usize,
+    edit: SimulationEdit,
+) {
+    if count as f32 > state.confirmation_threshold {
+        // The confirmation is shown in the info window
+        state.info_window_open = true;
+        state.pending_spawn = Some((count, edit));
+    } else {
+        thread_data.edits.push(edit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_all_stops_the_simulation_thread_modes() {
+        let path = std::env::temp_dir().join(format!(
+            "particle_life_reset_all_test_{}.csv",
+            std::process::id()
+        ));
+        let mut thread_data = SimulationThreadData {
+            grid_size: [3, 3],
+            steps: 50,
+            morph: Some(AttractionMorph {
+                target: Matrix::from_element([2, 2], 1.0),
+                steps_left: 10,
+            }),
+            time_dilation: Some(TimeDilation::default()),
+            recorder: Some(MetricRecorder::with_path(&path, Metric::TypeCounts, 1, 2).unwrap()),
+            orbits: Some(OrbitDetector::default()),
+            ..Default::default()
+        };
+        thread_data
+            .edits
+            .push(Box::new(|simulation| simulation.clear_particles()));
+        let mut settings = AppSettings {
+            tps_limit: 1000.0,
+            fps_limit: 30.0,
+            ..Default::default()
+        };
+        let mut state = UiState {
+            custom_tps: "1000".to_string(),
+            ..Default::default()
+        };
+
+        reset_all(&mut state, &mut settings, &mut thread_data);
+        assert!(thread_data.reset);
+        assert_eq!(thread_data.grid_size, DEFAULT_GRID_SIZE);
+        assert!(thread_data.edits.is_empty());
+        assert_eq!(thread_data.steps, 0);
+        assert!(thread_data.morph.is_none());
+        assert!(thread_data.time_dilation.is_none());
+        assert!(thread_data.recorder.is_none());
+        assert!(thread_data.orbits.is_none());
+        assert_eq!(settings.tps_limit, AppSettings::default().tps_limit);
+        assert_eq!(settings.fps_limit, 30.0);
+        assert!(state.custom_tps.is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reset_keeps_the_favorites() {
+        let favorite = |name: &str| Favorite {
+            name: name.to_string(),
+            type_data: ParticleTypeData::new_random(3, 1.0),
+        };
+        let mut state = UiState {
+            favorites: Favorites {
+                entries: vec![favorite("First"), favorite("Second")],
+            },
+            favorite_name: "Third".to_string(),
+            spawn_count: 5.0,
+            ..Default::default()
+        };
+        state.reset();
+        assert!(state.info_window_open);
+        assert_eq!(state.spawn_count, UiState::default().spawn_count);
+        assert!(state.favorite_name.is_empty());
+
+        // Saving a new favorite after the reset writes the earlier ones too
+        state.favorites.entries.push(favorite("Third"));
+        let saved = Favorites::parse(&state.favorites.to_string());
+        let names: Vec<_> = saved
+            .entries
+            .iter()
+            .map(|favorite| &favorite.name)
+            .collect();
+        assert_eq!(names, ["First", "Second", "Third"]);
+    }
+}
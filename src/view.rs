@@ -0,0 +1,41 @@
+use macroquad::{camera::Camera2D, input, math::Vec2, time, window};
+
+/// The position in the world under the mouse cursor.
+pub fn cursor_position(camera: &Camera2D) -> Vec2 {
+    camera.screen_to_world(input::mouse_position().into())
+}
+
+/// The number of pixels one unit of the world takes up on screen.
+pub fn pixels_per_unit(camera: &Camera2D) -> f32 {
+    camera.zoom.y * window::screen_height() / 2.0
+}
+
+/// Zooms the camera so that one unit of the world takes up `pixels_per_unit` pixels.
+pub fn set_pixels_per_unit(camera: &mut Camera2D, pixels_per_unit: f32) {
+    camera.zoom = Vec2::splat(pixels_per_unit * 2.0 / window::screen_height());
+    update_aspect_ratio(camera);
+}
+
+/// Matches the horizontal zoom to the vertical zoom, so that the world isn't stretched by the
+/// shape of the window.
+pub fn update_aspect_ratio(camera: &mut Camera2D) {
+    camera.zoom.x = camera.zoom.y * window::screen_height() / window::screen_width();
+}
+
+/// Centers the camera on `bounds` (from `ParticleSimulation::world_bounds`), zoomed to fit them.
+pub fn center_camera(camera: &mut Camera2D, bounds: (Vec2, Vec2)) {
+    let (min, max) = bounds;
+    let size = max - min;
+    camera.target = (min + max) / 2.0;
+    // Fit whichever axis fills the window first
+    let aspect_ratio = window::screen_height() / window::screen_width();
+    camera.zoom = Vec2::splat(2.0 / size.y.max(size.x * aspect_ratio));
+    update_aspect_ratio(camera);
+}
+
+/// Moves the center of the camera back towards `bounds` if it is outside of them.
+pub fn pull_camera_into_bounds(camera: &mut Camera2D, bounds: (Vec2, Vec2)) {
+    let inside = camera.target.clamp(bounds.0, bounds.1);
+    let amount = 1.0 - (-5.0 * time::get_frame_time()).exp();
+    camera.target = camera.target.lerp(inside, amount);
+}